@@ -0,0 +1,671 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufRead, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::MetadataExt,
+    },
+    path::Path,
+    process::Command,
+    time::SystemTime,
+};
+
+use chrono::{format::StrftimeItems, DateTime, Local};
+use users::{get_group_by_gid, get_user_by_uid};
+
+use crate::{
+    entry::Entry,
+    expr::{FileKind, TimeField},
+    io_error_message,
+};
+
+/// Upper bound for the combined length of the paths handed to a single `-exec ... {} +` run,
+/// well below the usual `ARG_MAX` so that the environment still fits next to them.
+const MAX_BATCH_BYTES: usize = 128 * 1024;
+
+#[derive(Debug)]
+pub enum Action {
+    Print,
+    Print0,
+    Printf(Vec<FormatPart>),
+    Delete,
+    Exec(ExecMode, Vec<String>),
+    Prune,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ExecMode {
+    Each,
+    Batch(usize),
+    Prompt,
+}
+
+#[derive(Debug)]
+pub enum FormatPart {
+    Literal(String),
+    Directive(Directive, Option<usize>, bool),
+}
+
+#[derive(Debug)]
+pub enum Directive {
+    Percent,
+    Path,
+    Name,
+    Dir,
+    RelativePath,
+    Root,
+    Size,
+    Kilobytes,
+    Blocks,
+    OctalMode,
+    SymbolicMode,
+    User,
+    Uid,
+    Group,
+    Gid,
+    Depth,
+    Type,
+    LinkTarget,
+    Inode,
+    Links,
+    Time(TimeField, TimeFormat),
+}
+
+#[derive(Debug)]
+pub enum TimeFormat {
+    Ctime,
+    Epoch,
+    Strftime(String),
+}
+
+struct Batch {
+    command: Vec<String>,
+    paths: Vec<OsString>,
+    size: usize,
+}
+
+pub struct Context<'a> {
+    now: SystemTime,
+    out: &'a mut dyn Write,
+    batches: BTreeMap<usize, Batch>,
+    error: Option<io::Error>,
+    pruned: bool,
+    failed: bool,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(now: SystemTime, out: &'a mut dyn Write) -> Self {
+        Self {
+            now,
+            out,
+            batches: BTreeMap::new(),
+            error: None,
+            pruned: false,
+            failed: false,
+        }
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.now
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// Whether `-prune` matched the entry that was just evaluated.
+    pub fn take_pruned(&mut self) -> bool {
+        std::mem::take(&mut self.pruned)
+    }
+
+    /// Output errors (a closed pipe, usually) can't be reported from within an expression, so
+    /// they are stashed here for the traversal to pick up and stop on.
+    pub fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs whatever is still pending from `-exec ... {} +` actions.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let ids: Vec<usize> = self.batches.keys().copied().collect();
+        for id in ids {
+            self.run_batch(id);
+        }
+
+        self.take_error()?;
+        self.out.flush()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+
+        match self.out.write_all(bytes) {
+            Ok(()) => true,
+            Err(err) => {
+                self.error = Some(err);
+                false
+            }
+        }
+    }
+
+    fn run(&mut self, argv: &[OsString]) -> bool {
+        if let Err(err) = self.out.flush() {
+            self.error = Some(err);
+            return false;
+        }
+
+        match Command::new(&argv[0]).args(&argv[1..]).status() {
+            Ok(status) => status.success(),
+            Err(err) => {
+                eprintln!(
+                    "find: '{}': {}",
+                    argv[0].to_string_lossy(),
+                    io_error_message(&err)
+                );
+                self.failed = true;
+                false
+            }
+        }
+    }
+
+    fn run_batch(&mut self, id: usize) {
+        let batch = match self.batches.remove(&id) {
+            Some(batch) if !batch.paths.is_empty() => batch,
+            _ => return,
+        };
+
+        let mut argv: Vec<OsString> = batch.command.iter().map(OsString::from).collect();
+        argv.extend(batch.paths);
+
+        if !self.run(&argv) {
+            self.failed = true;
+        }
+    }
+}
+
+impl Action {
+    pub fn evaluate(&self, entry: &Entry, ctx: &mut Context) -> bool {
+        match self {
+            Action::Print => {
+                let mut bytes = entry.path().as_os_str().as_bytes().to_vec();
+                bytes.push(b'\n');
+
+                ctx.write(&bytes)
+            }
+            Action::Print0 => {
+                let mut bytes = entry.path().as_os_str().as_bytes().to_vec();
+                bytes.push(b'\0');
+
+                ctx.write(&bytes)
+            }
+            Action::Printf(parts) => {
+                let mut bytes = Vec::new();
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(literal) => bytes.extend(literal.as_bytes()),
+                        FormatPart::Directive(directive, width, left_align) => {
+                            let value = directive.render(entry);
+                            let padding = width.unwrap_or(0).saturating_sub(value.len());
+
+                            if !left_align {
+                                bytes.extend(std::iter::repeat_n(b' ', padding));
+                            }
+                            bytes.extend(value);
+                            if *left_align {
+                                bytes.extend(std::iter::repeat_n(b' ', padding));
+                            }
+                        }
+                    }
+                }
+
+                ctx.write(&bytes)
+            }
+            Action::Delete => {
+                let path = entry.path();
+                if path == Path::new(".") {
+                    return true;
+                }
+
+                let result = if entry.metadata().is_dir() {
+                    fs::remove_dir(path)
+                } else {
+                    fs::remove_file(path)
+                };
+
+                match result {
+                    Ok(()) => true,
+                    Err(err) => {
+                        eprintln!(
+                            "find: cannot delete '{}': {}",
+                            path.display(),
+                            io_error_message(&err)
+                        );
+                        ctx.failed = true;
+                        false
+                    }
+                }
+            }
+            Action::Prune => {
+                ctx.pruned = true;
+                true
+            }
+            Action::Exec(ExecMode::Batch(id), command) => {
+                let path = entry.path().as_os_str();
+                let batch = ctx.batches.entry(*id).or_insert_with(|| Batch {
+                    command: command.clone(),
+                    paths: Vec::new(),
+                    size: 0,
+                });
+                batch.paths.push(path.to_os_string());
+                batch.size += path.len() + 1;
+
+                if batch.size >= MAX_BATCH_BYTES {
+                    ctx.run_batch(*id);
+                }
+
+                true
+            }
+            Action::Exec(mode, command) => {
+                let path = entry.path().as_os_str();
+                let argv: Vec<OsString> = command
+                    .iter()
+                    .map(|arg| replace_placeholder(arg, path))
+                    .collect();
+
+                if let ExecMode::Prompt = mode {
+                    if !confirm(&argv) {
+                        return false;
+                    }
+                }
+
+                ctx.run(&argv)
+            }
+        }
+    }
+}
+
+fn replace_placeholder(arg: &str, path: &OsStr) -> OsString {
+    let mut replaced = Vec::new();
+    let mut parts = arg.split("{}").peekable();
+
+    while let Some(part) = parts.next() {
+        replaced.extend(part.as_bytes());
+        if parts.peek().is_some() {
+            replaced.extend(path.as_bytes());
+        }
+    }
+
+    OsString::from(OsStr::from_bytes(&replaced))
+}
+
+fn confirm(argv: &[OsString]) -> bool {
+    let command = argv
+        .iter()
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    eprint!("< {} > ? ", command);
+
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => answer.trim_start().starts_with(['y', 'Y']),
+        Err(_) => false,
+    }
+}
+
+impl Directive {
+    fn render(&self, entry: &Entry) -> Vec<u8> {
+        let metadata = entry.metadata();
+        let path = entry.path();
+
+        match self {
+            Directive::Percent => b"%".to_vec(),
+            Directive::Path => path.as_os_str().as_bytes().to_vec(),
+            Directive::Name => entry.file_name().as_bytes().to_vec(),
+            Directive::Dir => match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => {
+                    parent.as_os_str().as_bytes().to_vec()
+                }
+                _ => b".".to_vec(),
+            },
+            Directive::RelativePath => path
+                .strip_prefix(entry.root())
+                .map(|relative| relative.as_os_str().as_bytes().to_vec())
+                .unwrap_or_default(),
+            Directive::Root => entry.root().as_os_str().as_bytes().to_vec(),
+            Directive::Size => metadata.size().to_string().into_bytes(),
+            Directive::Kilobytes => metadata.blocks().div_ceil(2).to_string().into_bytes(),
+            Directive::Blocks => metadata.blocks().to_string().into_bytes(),
+            Directive::OctalMode => format!("{:o}", metadata.mode() & 0o7777).into_bytes(),
+            Directive::SymbolicMode => symbolic_mode(
+                FileKind::from(metadata).type_char(),
+                metadata.mode(),
+            )
+            .into_bytes(),
+            Directive::User => match get_user_by_uid(metadata.uid()) {
+                Some(user) => user.name().as_bytes().to_vec(),
+                None => metadata.uid().to_string().into_bytes(),
+            },
+            Directive::Uid => metadata.uid().to_string().into_bytes(),
+            Directive::Group => match get_group_by_gid(metadata.gid()) {
+                Some(group) => group.name().as_bytes().to_vec(),
+                None => metadata.gid().to_string().into_bytes(),
+            },
+            Directive::Gid => metadata.gid().to_string().into_bytes(),
+            Directive::Depth => entry.depth().to_string().into_bytes(),
+            Directive::Type => vec![FileKind::from(metadata).type_char() as u8],
+            Directive::LinkTarget => fs::read_link(path)
+                .map(|target| target.into_os_string().into_vec())
+                .unwrap_or_default(),
+            Directive::Inode => metadata.ino().to_string().into_bytes(),
+            Directive::Links => metadata.nlink().to_string().into_bytes(),
+            Directive::Time(field, format) => {
+                let (seconds, nanoseconds) = match field {
+                    TimeField::Accessed => (metadata.atime(), metadata.atime_nsec()),
+                    TimeField::Changed => (metadata.ctime(), metadata.ctime_nsec()),
+                    TimeField::Modified => (metadata.mtime(), metadata.mtime_nsec()),
+                };
+
+                let datetime = match DateTime::from_timestamp(seconds, nanoseconds as u32) {
+                    Some(datetime) => datetime.with_timezone(&Local),
+                    None => return Vec::new(),
+                };
+
+                match format {
+                    TimeFormat::Ctime => datetime.format("%a %b %e %H:%M:%S %Y").to_string(),
+                    TimeFormat::Epoch => format!("{}.{:09}", seconds, nanoseconds),
+                    TimeFormat::Strftime(format) => datetime.format(format).to_string(),
+                }
+                .into_bytes()
+            }
+        }
+    }
+}
+
+pub fn symbolic_mode(type_char: char, mode: u32) -> String {
+    let mut string = String::from(if type_char == 'f' { '-' } else { type_char });
+
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+
+        string.push(if bits & 0o4 > 0 { 'r' } else { '-' });
+        string.push(if bits & 0o2 > 0 { 'w' } else { '-' });
+        string.push(match (mode & special > 0, bits & 0o1 > 0) {
+            (true, true) => special_char,
+            (true, false) => special_char.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+
+    string
+}
+
+pub fn parse_format(format: &str) -> Result<Vec<FormatPart>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => literal.push('\n'),
+                Some('t') => literal.push('\t'),
+                Some('r') => literal.push('\r'),
+                Some('a') => literal.push('\x07'),
+                Some('b') => literal.push('\x08'),
+                Some('f') => literal.push('\x0c'),
+                Some('v') => literal.push('\x0b'),
+                Some('\\') => literal.push('\\'),
+                Some(digit @ '0'..='7') => {
+                    let mut value = digit.to_digit(8).unwrap();
+                    for _ in 0..2 {
+                        match chars.next_if(|c| c.is_digit(8)) {
+                            Some(digit) => value = value * 8 + digit.to_digit(8).unwrap(),
+                            None => break,
+                        }
+                    }
+                    literal.push(char::from_u32(value).unwrap_or('\0'));
+                }
+                Some(other) => {
+                    return Err(format!("warning: unrecognized escape `\\{}'", other));
+                }
+                None => literal.push('\\'),
+            },
+            '%' => {
+                let left_align = chars.next_if_eq(&'-').is_some();
+                let mut width = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    width.push(digit);
+                }
+
+                let directive = match chars.next() {
+                    Some('%') => Directive::Percent,
+                    Some('p') => Directive::Path,
+                    Some('f') => Directive::Name,
+                    Some('h') => Directive::Dir,
+                    Some('P') => Directive::RelativePath,
+                    Some('H') => Directive::Root,
+                    Some('s') => Directive::Size,
+                    Some('k') => Directive::Kilobytes,
+                    Some('b') => Directive::Blocks,
+                    Some('m') => Directive::OctalMode,
+                    Some('M') => Directive::SymbolicMode,
+                    Some('u') => Directive::User,
+                    Some('U') => Directive::Uid,
+                    Some('g') => Directive::Group,
+                    Some('G') => Directive::Gid,
+                    Some('d') => Directive::Depth,
+                    Some('y') => Directive::Type,
+                    Some('l') => Directive::LinkTarget,
+                    Some('i') => Directive::Inode,
+                    Some('n') => Directive::Links,
+                    Some('a') => Directive::Time(TimeField::Accessed, TimeFormat::Ctime),
+                    Some('c') => Directive::Time(TimeField::Changed, TimeFormat::Ctime),
+                    Some('t') => Directive::Time(TimeField::Modified, TimeFormat::Ctime),
+                    Some(field @ ('A' | 'C' | 'T')) => {
+                        let field = match field {
+                            'A' => TimeField::Accessed,
+                            'C' => TimeField::Changed,
+                            _ => TimeField::Modified,
+                        };
+
+                        Directive::Time(field, parse_time_format(chars.next())?)
+                    }
+                    Some(other) => {
+                        return Err(format!("invalid format directive `%{}'", other));
+                    }
+                    None => return Err(String::from("missing format directive after `%'")),
+                };
+
+                if !literal.is_empty() {
+                    parts.push(FormatPart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(FormatPart::Directive(
+                    directive,
+                    width.parse().ok(),
+                    left_align,
+                ));
+            }
+            _ => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(FormatPart::Literal(literal));
+    }
+
+    Ok(parts)
+}
+
+fn parse_time_format(spec: Option<char>) -> Result<TimeFormat, String> {
+    match spec {
+        Some('@') => Ok(TimeFormat::Epoch),
+        Some('+') => Ok(TimeFormat::Strftime(String::from("%Y-%m-%d+%H:%M:%S"))),
+        Some(spec) => {
+            let format = format!("%{}", spec);
+            let is_valid = StrftimeItems::new(&format)
+                .all(|item| !matches!(item, chrono::format::Item::Error));
+
+            if is_valid {
+                Ok(TimeFormat::Strftime(format))
+            } else {
+                Err(format!("invalid time format `{}'", spec))
+            }
+        }
+        None => Err(String::from("missing time format specifier")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::SystemTime,
+    };
+
+    use clap::Parser;
+
+    use super::{parse_format, Action, Context, ExecMode, MAX_BATCH_BYTES};
+    use crate::{entry::Entry, FindCommand};
+
+    fn entry(path: &Path) -> Entry {
+        let metadata = fs::symlink_metadata(path).unwrap();
+        Entry::new(Arc::from(path), path.to_path_buf(), 0, metadata)
+    }
+
+    /// Evaluates `action` for each path, then finishes, and returns what was printed, whether
+    /// the action held for each path and whether anything failed.
+    fn evaluate(action: &Action, entries: &[Entry]) -> (String, Vec<bool>, bool) {
+        let mut out = Vec::new();
+        let mut ctx = Context::new(SystemTime::now(), &mut out);
+        let results = entries
+            .iter()
+            .map(|entry| action.evaluate(entry, &mut ctx))
+            .collect();
+        ctx.finish().unwrap();
+        let failed = ctx.failed();
+
+        (String::from_utf8(out).unwrap(), results, failed)
+    }
+
+    fn sh(script: &str) -> Vec<String> {
+        ["sh", "-c", script, "sh"].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn test_printf() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ab");
+        fs::write(&path, "xyz").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let action = Action::Printf(parse_format("[%-5f|%5s|%m|%M|%y|%d]\\n%%\\t%3p").unwrap());
+        let (out, results, failed) = evaluate(&action, &[entry(&path)]);
+
+        assert_eq!(
+            out,
+            format!("[ab   |    3|640|-rw-r-----|f|0]\n%\t{}", path.display())
+        );
+        assert_eq!(results, [true]);
+        assert!(!failed);
+
+        assert!(parse_format("%z").is_err());
+        assert!(parse_format("%").is_err());
+        assert!(parse_format("%T").is_err());
+    }
+
+    #[test]
+    fn test_exec_each() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        let entries = [entry(dir.path()), entry(dir.path())];
+
+        // like find, a command that fails only makes -exec false, the exit status stays 0
+        let script = format!("echo \"$1\" >> '{}'; exit 1", log.display());
+        let mut command = sh(&script);
+        command.push(String::from("{}"));
+        let (_, results, failed) = evaluate(&Action::Exec(ExecMode::Each, command), &entries);
+
+        assert_eq!(results, [false, false]);
+        assert!(!failed);
+        assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 2);
+
+        let (_, results, failed) = evaluate(&Action::Exec(ExecMode::Each, sh("exit 0")), &entries);
+        assert_eq!(results, [true, true]);
+        assert!(!failed);
+    }
+
+    #[test]
+    fn test_exec_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+
+        // a batch that fails does change the exit status
+        let (_, results, failed) = evaluate(
+            &Action::Exec(ExecMode::Batch(0), sh("exit 1")),
+            &[entry(dir.path())],
+        );
+        assert_eq!(results, [true]);
+        assert!(failed);
+
+        // paths long enough that a few of them fill a batch
+        let long = dir.path().join("x".repeat(200));
+        fs::write(&long, "").unwrap();
+        let name = long.as_os_str().len() + 1;
+        let count = MAX_BATCH_BYTES.div_ceil(name) * 2 + 1;
+        let entries: Vec<Entry> = (0..count).map(|_| entry(&long)).collect();
+
+        let script = format!("echo $# >> '{}'", log.display());
+        let (_, _, failed) = evaluate(&Action::Exec(ExecMode::Batch(0), sh(&script)), &entries);
+        assert!(!failed);
+
+        let runs: Vec<usize> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs.iter().sum::<usize>(), count);
+        assert!(runs.iter().all(|run| run * name <= MAX_BATCH_BYTES + name));
+    }
+
+    #[test]
+    fn test_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/c"), "").unwrap();
+        fs::write(root.join("d"), "").unwrap();
+
+        // -delete walks depth first, so directories are empty by the time they're deleted
+        let cmd = FindCommand::try_parse_from([
+            PathBuf::from("find"),
+            root.clone(),
+            PathBuf::from("-delete"),
+        ])
+        .unwrap();
+        let mut out = Vec::new();
+        assert_eq!(cmd.exec(&mut out).unwrap().exit_code(), 0);
+        assert!(!root.exists());
+
+        // `.` is never deleted
+        let (_, results, failed) = evaluate(&Action::Delete, &[entry(Path::new("."))]);
+        assert_eq!(results, [true]);
+        assert!(!failed);
+        assert!(Path::new(".").exists());
+    }
+}
@@ -0,0 +1,18 @@
+use std::{
+    io::{self, BufWriter},
+    process,
+};
+
+use clap::Parser;
+use color_eyre::Result;
+use find::LocateCommand;
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let cmd = LocateCommand::parse();
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = cmd.exec(&mut out)?;
+
+    process::exit(result.exit_code());
+}
@@ -0,0 +1,14 @@
+use std::process;
+
+use clap::Parser;
+use color_eyre::Result;
+use find::UpdatedbCommand;
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let cmd = UpdatedbCommand::parse();
+    let result = cmd.exec()?;
+
+    process::exit(result.exit_code());
+}
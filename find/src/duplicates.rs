@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    hash::Hash,
+    io::{self, Read},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::entry::Entry;
+
+/// Files of at most this many bytes are compared by their full hash straight away.
+const PARTIAL_HASH_BYTES: u64 = 4096;
+
+/// One file on disk, under every path it was found at.
+#[derive(Debug)]
+pub struct Inode {
+    paths: Vec<PathBuf>,
+    size: u64,
+}
+
+impl Inode {
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+/// Collects regular files and sorts out which of them have the same contents.
+///
+/// Candidates are narrowed down by size first, then by a hash of their first few kilobytes and
+/// only then by a hash of everything, so most files are never read in full. Hard links to the
+/// same file are one file: reading it twice would be pointless and linking it to itself more so.
+#[derive(Default)]
+pub struct Duplicates {
+    inodes: Vec<Inode>,
+    by_id: HashMap<(u64, u64), usize>,
+}
+
+impl Duplicates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Anything but non-empty regular files is ignored; every empty file is a copy of every other.
+    pub fn add(&mut self, entry: &Entry) {
+        let metadata = entry.metadata();
+        if !metadata.is_file() || metadata.len() == 0 {
+            return;
+        }
+
+        let id = (metadata.dev(), metadata.ino());
+        match self.by_id.get(&id) {
+            Some(&idx) => self.inodes[idx].paths.push(entry.path().to_path_buf()),
+            None => {
+                self.by_id.insert(id, self.inodes.len());
+                self.inodes.push(Inode {
+                    paths: vec![entry.path().to_path_buf()],
+                    size: metadata.len(),
+                });
+            }
+        }
+    }
+
+    /// Returns every set of files with the same contents, biggest files first. Sets only made up
+    /// of hard links to one file are included when `links_are_duplicates`. Files that can't be
+    /// read are passed to `on_error` and left out.
+    pub fn sets(
+        self,
+        links_are_duplicates: bool,
+        on_error: &mut dyn FnMut(&Path, io::Error),
+    ) -> Vec<Vec<Inode>> {
+        let mut sets = Vec::new();
+
+        for group in group_by(self.inodes, |inode| inode.size) {
+            if group.len() == 1 {
+                sets.push(group);
+                continue;
+            }
+
+            let group = if group[0].size > PARTIAL_HASH_BYTES {
+                hash_all(group, Some(PARTIAL_HASH_BYTES), on_error)
+            } else {
+                vec![group]
+            };
+
+            for group in group {
+                if group.len() == 1 {
+                    sets.push(group);
+                } else {
+                    sets.extend(hash_all(group, None, on_error));
+                }
+            }
+        }
+
+        sets.retain(|set| {
+            set.len() > 1 || (links_are_duplicates && set.iter().any(|inode| inode.paths.len() > 1))
+        });
+        for set in &mut sets {
+            for inode in set.iter_mut() {
+                inode.paths.sort();
+            }
+            set.sort_by(|a, b| a.paths.cmp(&b.paths));
+        }
+        sets.sort_by(|a, b| {
+            b[0].size
+                .cmp(&a[0].size)
+                .then_with(|| a[0].paths.cmp(&b[0].paths))
+        });
+
+        sets
+    }
+}
+
+/// Replaces every file in the set with a hard link to its first one, leaving a file alone if
+/// anything goes wrong with it, and returns the paths that couldn't be replaced.
+pub fn hardlink(set: &[Inode]) -> Vec<(PathBuf, io::Error)> {
+    let target = &set[0].paths[0];
+    let mut errors = Vec::new();
+
+    for path in set[1..].iter().flat_map(|inode| &inode.paths) {
+        if let Err(err) = replace_with_link(target, path) {
+            errors.push((path.clone(), err));
+        }
+    }
+
+    errors
+}
+
+/// The link is made next to the file and renamed over it, so the file is never missing.
+fn replace_with_link(target: &Path, path: &Path) -> io::Result<()> {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".find-link");
+    let tmp = path.with_file_name(name);
+
+    fs::hard_link(target, &tmp)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+fn hash_all(
+    inodes: Vec<Inode>,
+    limit: Option<u64>,
+    on_error: &mut dyn FnMut(&Path, io::Error),
+) -> Vec<Vec<Inode>> {
+    let hashed: Vec<(blake3::Hash, Inode)> = inodes
+        .into_iter()
+        .filter_map(|inode| match hash(&inode.paths[0], limit) {
+            Ok(hash) => Some((hash, inode)),
+            Err(err) => {
+                on_error(&inode.paths[0], err);
+                None
+            }
+        })
+        .collect();
+
+    group_by(hashed, |(hash, _)| *hash)
+        .into_iter()
+        .map(|group| group.into_iter().map(|(_, inode)| inode).collect())
+        .collect()
+}
+
+fn hash(path: &Path, limit: Option<u64>) -> io::Result<blake3::Hash> {
+    let file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+
+    match limit {
+        Some(limit) => io::copy(&mut file.take(limit), &mut hasher)?,
+        None => io::copy(&mut &file, &mut hasher)?,
+    };
+
+    Ok(hasher.finalize())
+}
+
+/// Groups in order of first appearance, so the output doesn't depend on hashing order.
+fn group_by<T, K: Eq + Hash>(items: Vec<T>, key: impl Fn(&T) -> K) -> Vec<Vec<T>> {
+    let mut groups: Vec<Vec<T>> = Vec::new();
+    let mut index = HashMap::new();
+
+    for item in items {
+        let idx = *index.entry(key(&item)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[idx].push(item);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::MetadataExt, path::PathBuf};
+
+    use super::{hardlink, Duplicates};
+    use crate::Walker;
+
+    fn sets(root: &std::path::Path, links_are_duplicates: bool) -> Vec<Vec<Vec<PathBuf>>> {
+        let mut duplicates = Duplicates::new();
+        for entry in Walker::new(root).build().flatten() {
+            duplicates.add(&entry);
+        }
+
+        duplicates
+            .sets(links_are_duplicates, &mut |path, err| {
+                panic!("{}: {}", path.display(), err)
+            })
+            .into_iter()
+            .map(|set| set.into_iter().map(|inode| inode.paths).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let big = "x".repeat(10_000);
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+        fs::write(root.join("c"), "diff").unwrap();
+        fs::hard_link(root.join("a"), root.join("d")).unwrap();
+        fs::write(root.join("e"), "").unwrap();
+        fs::write(root.join("f"), "").unwrap();
+        fs::write(root.join("g"), &big).unwrap();
+        fs::write(root.join("h"), format!("{}y", &big[1..])).unwrap();
+        fs::write(root.join("i"), format!("{}y", &big[1..])).unwrap();
+
+        assert_eq!(
+            sets(root, false),
+            vec![
+                vec![vec![root.join("h")], vec![root.join("i")]],
+                vec![vec![root.join("a"), root.join("d")], vec![root.join("b")]],
+            ]
+        );
+
+        fs::remove_file(root.join("b")).unwrap();
+        assert_eq!(sets(root, false).len(), 1);
+        assert_eq!(
+            sets(root, true)[1],
+            vec![vec![root.join("a"), root.join("d")]]
+        );
+    }
+
+    #[test]
+    fn test_hardlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+
+        let mut duplicates = Duplicates::new();
+        for entry in Walker::new(root).build().flatten() {
+            duplicates.add(&entry);
+        }
+        let set = duplicates.sets(false, &mut |_, _| {}).remove(0);
+
+        assert!(hardlink(&set).is_empty());
+        let a = fs::metadata(root.join("a")).unwrap();
+        let b = fs::metadata(root.join("b")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(fs::read_dir(root).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    ffi::OsStr,
+    fs::{FileType, Metadata},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A file found by a walk, along with the metadata looked up for it on the way.
+pub struct Entry {
+    root: Arc<Path>,
+    path: PathBuf,
+    depth: usize,
+    metadata: Metadata,
+}
+
+impl Entry {
+    pub(crate) fn new(root: Arc<Path>, path: PathBuf, depth: usize, metadata: Metadata) -> Self {
+        Self {
+            root,
+            path,
+            depth,
+            metadata,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The last component of the path, or the whole path for roots like `.` and `/`.
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.metadata.file_type()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
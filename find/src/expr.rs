@@ -0,0 +1,799 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::{self, Metadata},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    time::SystemTime,
+};
+
+use glob::{MatchOptions, Pattern};
+use regex::bytes::{Regex, RegexBuilder};
+use users::{get_group_by_name, get_user_by_name};
+
+use crate::{
+    action::{self, Action, Context, ExecMode},
+    entry::Entry,
+};
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Test(Test),
+    Action(Action),
+}
+
+#[derive(Debug)]
+pub enum Test {
+    True,
+    False,
+    Name(Pattern, bool),
+    Path(Pattern, bool),
+    Regex(Regex),
+    Type(Vec<FileKind>),
+    Size(Comparison, u64),
+    Time(TimeField, Comparison, u64),
+    Newer(SystemTime),
+    Perm(PermMatch, u32),
+    User(u32),
+    Group(u32),
+    Empty,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    LessThan(u64),
+    Exactly(u64),
+    GreaterThan(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeField {
+    Accessed,
+    Changed,
+    Modified,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PermMatch {
+    Exact,
+    AllOf,
+    AnyOf,
+}
+
+/// Settings that come in through the expression (`-maxdepth`, `-xdev`, ...) but steer the walk
+/// rather than test entries.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub max_depth: Option<usize>,
+    pub min_depth: usize,
+    pub depth_first: bool,
+    pub same_file_system: bool,
+}
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+impl Expr {
+    pub fn parse(args: &[String]) -> ParseResult<(Self, Options)> {
+        if args.is_empty() {
+            return Ok((Expr::Action(Action::Print), Options::default()));
+        }
+
+        let mut parser = Parser {
+            args,
+            position: 0,
+            batches: 0,
+            options: Options::default(),
+        };
+        let expr = parser.parse_or()?;
+
+        match parser.peek() {
+            None => {}
+            Some(")") => {
+                return Err(ParseError(String::from(
+                    "invalid expression; you have too many ')'",
+                )))
+            }
+            Some(arg) => return Err(ParseError(format!("unexpected argument '{}'", arg))),
+        }
+
+        let mut options = parser.options;
+        // `-delete` has to see a directory's contents gone before the directory itself.
+        if expr.any_action(&|action| matches!(action, Action::Delete)) {
+            options.depth_first = true;
+        }
+
+        if expr.any_action(&|action| !matches!(action, Action::Prune)) {
+            Ok((expr, options))
+        } else {
+            let expr = Expr::And(Box::new(expr), Box::new(Expr::Action(Action::Print)));
+
+            Ok((expr, options))
+        }
+    }
+
+    /// Whether evaluating the expression can touch anything besides its output.
+    pub fn has_side_effects(&self) -> bool {
+        self.any_action(&|action| matches!(action, Action::Delete | Action::Exec(..)))
+    }
+
+    fn any_action(&self, predicate: &dyn Fn(&Action) -> bool) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.any_action(predicate) || right.any_action(predicate)
+            }
+            Expr::Not(expr) => expr.any_action(predicate),
+            Expr::Test(_) => false,
+            Expr::Action(action) => predicate(action),
+        }
+    }
+
+    pub fn evaluate(&self, entry: &Entry, ctx: &mut Context) -> bool {
+        match self {
+            Expr::And(left, right) => left.evaluate(entry, ctx) && right.evaluate(entry, ctx),
+            Expr::Or(left, right) => left.evaluate(entry, ctx) || right.evaluate(entry, ctx),
+            Expr::Not(expr) => !expr.evaluate(entry, ctx),
+            Expr::Test(test) => test.evaluate(entry, ctx.now()),
+            Expr::Action(action) => action.evaluate(entry, ctx),
+        }
+    }
+}
+
+impl Test {
+    fn evaluate(&self, entry: &Entry, now: SystemTime) -> bool {
+        let path = entry.path();
+        let metadata = entry.metadata();
+
+        match self {
+            Test::True => true,
+            Test::False => false,
+            Test::Name(pattern, case_sensitive) => pattern.matches_with(
+                &entry.file_name().to_string_lossy(),
+                match_options(*case_sensitive),
+            ),
+            Test::Path(pattern, case_sensitive) => {
+                pattern.matches_with(&path.to_string_lossy(), match_options(*case_sensitive))
+            }
+            Test::Regex(regex) => regex.is_match(path.as_os_str().as_bytes()),
+            Test::Type(kinds) => kinds.contains(&FileKind::from(metadata)),
+            Test::Size(comparison, unit) => comparison.matches(metadata.size().div_ceil(*unit)),
+            Test::Time(field, comparison, period) => {
+                let seconds = match field {
+                    TimeField::Accessed => metadata.atime(),
+                    TimeField::Changed => metadata.ctime(),
+                    TimeField::Modified => metadata.mtime(),
+                };
+                let now = now
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|duration| duration.as_secs() as i64)
+                    .unwrap_or(0);
+                let age = now.saturating_sub(seconds).max(0) as u64;
+
+                comparison.matches(age / period)
+            }
+            Test::Newer(reference) => metadata
+                .modified()
+                .map(|modified| modified > *reference)
+                .unwrap_or(false),
+            Test::Perm(perm_match, mode) => {
+                let file_mode = metadata.mode() & 0o7777;
+
+                match perm_match {
+                    PermMatch::Exact => file_mode == *mode,
+                    PermMatch::AllOf => file_mode & mode == *mode,
+                    PermMatch::AnyOf => *mode == 0 || file_mode & mode > 0,
+                }
+            }
+            Test::User(uid) => metadata.uid() == *uid,
+            Test::Group(gid) => metadata.gid() == *gid,
+            Test::Empty => {
+                if metadata.is_dir() {
+                    fs::read_dir(path)
+                        .map(|mut dir| dir.next().is_none())
+                        .unwrap_or(false)
+                } else {
+                    metadata.is_file() && metadata.size() == 0
+                }
+            }
+        }
+    }
+}
+
+impl From<&Metadata> for FileKind {
+    fn from(metadata: &Metadata) -> Self {
+        let file_type = metadata.file_type();
+
+        if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_block_device() {
+            FileKind::BlockDevice
+        } else if file_type.is_char_device() {
+            FileKind::CharDevice
+        } else if file_type.is_fifo() {
+            FileKind::Fifo
+        } else if file_type.is_socket() {
+            FileKind::Socket
+        } else {
+            FileKind::File
+        }
+    }
+}
+
+impl FileKind {
+    pub fn type_char(&self) -> char {
+        match self {
+            FileKind::File => 'f',
+            FileKind::Dir => 'd',
+            FileKind::Symlink => 'l',
+            FileKind::BlockDevice => 'b',
+            FileKind::CharDevice => 'c',
+            FileKind::Fifo => 'p',
+            FileKind::Socket => 's',
+        }
+    }
+
+    fn parse(arg: &str) -> ParseResult<Vec<Self>> {
+        arg.split(',')
+            .map(|kind| match kind {
+                "f" => Ok(FileKind::File),
+                "d" => Ok(FileKind::Dir),
+                "l" => Ok(FileKind::Symlink),
+                "b" => Ok(FileKind::BlockDevice),
+                "c" => Ok(FileKind::CharDevice),
+                "p" => Ok(FileKind::Fifo),
+                "s" => Ok(FileKind::Socket),
+                _ => Err(ParseError(format!("Unknown argument to -type: {}", kind))),
+            })
+            .collect()
+    }
+}
+
+impl Comparison {
+    fn parse(arg: &str) -> Option<(Self, &str)> {
+        let (make, rest): (fn(u64) -> Self, &str) = match arg.strip_prefix('+') {
+            Some(rest) => (Comparison::GreaterThan, rest),
+            None => match arg.strip_prefix('-') {
+                Some(rest) => (Comparison::LessThan, rest),
+                None => (Comparison::Exactly, arg),
+            },
+        };
+
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits_end == 0 {
+            return None;
+        }
+
+        let value = rest[..digits_end].parse().ok()?;
+
+        Some((make(value), &rest[digits_end..]))
+    }
+
+    fn matches(&self, value: u64) -> bool {
+        match self {
+            Comparison::LessThan(n) => value < *n,
+            Comparison::Exactly(n) => value == *n,
+            Comparison::GreaterThan(n) => value > *n,
+        }
+    }
+}
+
+pub(crate) fn match_options(case_sensitive: bool) -> MatchOptions {
+    MatchOptions {
+        case_sensitive,
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    }
+}
+
+fn parse_mode(arg: &str) -> Option<u32> {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_digit(8)) {
+        return u32::from_str_radix(arg, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777);
+    }
+
+    let mut mode = 0;
+
+    for clause in arg.split(',') {
+        let who_end = clause
+            .find(|c| !matches!(c, 'u' | 'g' | 'o' | 'a'))
+            .unwrap_or(clause.len());
+        let (who, actions) = clause.split_at(who_end);
+
+        let who_mask = if who.is_empty() {
+            0o7777
+        } else {
+            who.chars().fold(0, |mask, c| {
+                mask | match c {
+                    'u' => 0o4700,
+                    'g' => 0o2070,
+                    'o' => 0o1007,
+                    _ => 0o7777,
+                }
+            })
+        };
+
+        if actions.is_empty() {
+            return None;
+        }
+
+        let mut chars = actions.chars().peekable();
+
+        while let Some(op) = chars.next() {
+            let mut bits = 0;
+            while let Some(c) = chars.next_if(|c| !matches!(c, '+' | '-' | '=')) {
+                bits |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' | 'X' => 0o111,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    _ => return None,
+                };
+            }
+            let bits = bits & who_mask;
+
+            match op {
+                '+' => mode |= bits,
+                '-' => mode &= !bits,
+                '=' => mode = (mode & !who_mask) | bits,
+                _ => return None,
+            }
+        }
+    }
+
+    Some(mode)
+}
+
+struct Parser<'a> {
+    args: &'a [String],
+    position: usize,
+    batches: usize,
+    options: Options,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.peek();
+        if arg.is_some() {
+            self.position += 1;
+        }
+
+        arg
+    }
+
+    fn next_argument(&mut self, predicate: &str) -> ParseResult<&'a str> {
+        self.next()
+            .ok_or_else(|| ParseError(format!("missing argument to `{}'", predicate)))
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_and()?;
+
+        while let Some(op @ ("-o" | "-or")) = self.peek() {
+            self.next();
+            if matches!(self.peek(), None | Some(")")) {
+                return Err(ParseError(format!(
+                    "invalid expression; expected an expression after '{}'",
+                    op
+                )));
+            }
+
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_not()?;
+
+        loop {
+            match self.peek() {
+                None | Some("-o" | "-or" | ")") => break,
+                Some(op @ ("-a" | "-and")) => {
+                    self.next();
+                    if matches!(self.peek(), None | Some(")" | "-o" | "-or")) {
+                        return Err(ParseError(format!(
+                            "invalid expression; expected an expression after '{}'",
+                            op
+                        )));
+                    }
+                }
+                Some(_) => {}
+            }
+
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> ParseResult<Expr> {
+        match self.peek() {
+            Some("!" | "-not") => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.parse_not()?)))
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let predicate = match self.next() {
+            Some(predicate) => predicate,
+            None => {
+                return Err(ParseError(String::from(
+                    "invalid expression; expected an expression",
+                )))
+            }
+        };
+
+        let test = match predicate {
+            "(" => {
+                if self.peek() == Some(")") {
+                    return Err(ParseError(String::from(
+                        "invalid expression; empty parentheses are not allowed.",
+                    )));
+                }
+
+                let expr = self.parse_or()?;
+
+                return match self.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err(ParseError(String::from(
+                        "invalid expression; I was expecting to find a ')' somewhere but did not see one.",
+                    ))),
+                };
+            }
+            "-o" | "-or" | "-a" | "-and" => {
+                return Err(ParseError(format!(
+                    "invalid expression; you have used a binary operator '{}' with nothing before it.",
+                    predicate
+                )))
+            }
+            ")" => {
+                return Err(ParseError(String::from(
+                    "invalid expression; you have too many ')'",
+                )))
+            }
+            "-true" => Test::True,
+            "-false" => Test::False,
+            "-name" | "-iname" | "-path" | "-ipath" | "-wholename" | "-iwholename" => {
+                let arg = self.next_argument(predicate)?;
+                let pattern = Pattern::new(arg).map_err(|err| {
+                    ParseError(format!("invalid pattern '{}' for {}: {}", arg, predicate, err))
+                })?;
+                let case_sensitive = !predicate.starts_with("-i");
+
+                if predicate.ends_with("name") && !predicate.ends_with("wholename") {
+                    Test::Name(pattern, case_sensitive)
+                } else {
+                    Test::Path(pattern, case_sensitive)
+                }
+            }
+            "-regex" | "-iregex" => {
+                let arg = self.next_argument(predicate)?;
+                let regex = RegexBuilder::new(&format!("^(?:{})$", arg))
+                    .case_insensitive(predicate == "-iregex")
+                    .unicode(false)
+                    .build()
+                    .map_err(|err| ParseError(format!("invalid regular expression: {}", err)))?;
+
+                Test::Regex(regex)
+            }
+            "-type" => Test::Type(FileKind::parse(self.next_argument(predicate)?)?),
+            "-size" => {
+                let arg = self.next_argument(predicate)?;
+                let invalid = || ParseError(format!("invalid -size type: `{}'", arg));
+                let (comparison, suffix) = Comparison::parse(arg).ok_or_else(invalid)?;
+                let unit = match suffix {
+                    "" | "b" => 512,
+                    "c" => 1,
+                    "w" => 2,
+                    "k" => 1024,
+                    "M" => 1024 * 1024,
+                    "G" => 1024 * 1024 * 1024,
+                    _ => return Err(invalid()),
+                };
+
+                Test::Size(comparison, unit)
+            }
+            "-amin" | "-cmin" | "-mmin" | "-atime" | "-ctime" | "-mtime" => {
+                let arg = self.next_argument(predicate)?;
+                let comparison = match Comparison::parse(arg) {
+                    Some((comparison, "")) => comparison,
+                    _ => {
+                        return Err(ParseError(format!(
+                            "invalid argument `{}' to `{}'",
+                            arg, predicate
+                        )))
+                    }
+                };
+                let field = match &predicate[1..2] {
+                    "a" => TimeField::Accessed,
+                    "c" => TimeField::Changed,
+                    _ => TimeField::Modified,
+                };
+                let period = if predicate.ends_with("min") {
+                    SECONDS_PER_MINUTE
+                } else {
+                    SECONDS_PER_DAY
+                };
+
+                Test::Time(field, comparison, period)
+            }
+            "-newer" => {
+                let arg = self.next_argument(predicate)?;
+                let modified = fs::metadata(arg)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|err| ParseError(format!("'{}': {}", arg, err)))?;
+
+                Test::Newer(modified)
+            }
+            "-perm" => {
+                let arg = self.next_argument(predicate)?;
+                let (perm_match, mode) = match arg.strip_prefix('-') {
+                    Some(mode) => (PermMatch::AllOf, mode),
+                    None => match arg.strip_prefix('/') {
+                        Some(mode) => (PermMatch::AnyOf, mode),
+                        None => (PermMatch::Exact, arg),
+                    },
+                };
+                let mode = parse_mode(mode)
+                    .ok_or_else(|| ParseError(format!("invalid mode '{}'", arg)))?;
+
+                Test::Perm(perm_match, mode)
+            }
+            "-user" => {
+                let arg = self.next_argument(predicate)?;
+                let uid = match get_user_by_name(arg) {
+                    Some(user) => user.uid(),
+                    None => arg.parse().map_err(|_| {
+                        ParseError(format!("'{}' is not the name of a known user", arg))
+                    })?,
+                };
+
+                Test::User(uid)
+            }
+            "-group" => {
+                let arg = self.next_argument(predicate)?;
+                let gid = match get_group_by_name(arg) {
+                    Some(group) => group.gid(),
+                    None => arg.parse().map_err(|_| {
+                        ParseError(format!("'{}' is not the name of an existing group", arg))
+                    })?,
+                };
+
+                Test::Group(gid)
+            }
+            "-empty" => Test::Empty,
+            "-maxdepth" | "-mindepth" => {
+                let arg = self.next_argument(predicate)?;
+                let depth = arg.parse().map_err(|_| {
+                    ParseError(format!(
+                        "Expected a positive decimal integer argument to {}, but got `{}'",
+                        predicate, arg
+                    ))
+                })?;
+
+                if predicate == "-maxdepth" {
+                    self.options.max_depth = Some(depth);
+                } else {
+                    self.options.min_depth = depth;
+                }
+
+                Test::True
+            }
+            "-depth" => {
+                self.options.depth_first = true;
+                Test::True
+            }
+            "-xdev" | "-mount" => {
+                self.options.same_file_system = true;
+                Test::True
+            }
+            "-prune" => return Ok(Expr::Action(Action::Prune)),
+            "-print" => return Ok(Expr::Action(Action::Print)),
+            "-print0" => return Ok(Expr::Action(Action::Print0)),
+            "-printf" => {
+                let format = self.next_argument(predicate)?;
+                let parts = action::parse_format(format).map_err(ParseError)?;
+
+                return Ok(Expr::Action(Action::Printf(parts)));
+            }
+            "-delete" => return Ok(Expr::Action(Action::Delete)),
+            "-exec" | "-ok" => return self.parse_exec(predicate),
+            _ => return Err(ParseError(format!("unknown predicate `{}'", predicate))),
+        };
+
+        Ok(Expr::Test(test))
+    }
+
+    fn parse_exec(&mut self, predicate: &str) -> ParseResult<Expr> {
+        let mut command = Vec::new();
+
+        loop {
+            match self.next() {
+                Some(";") => break,
+                Some("+") if command.last().is_some_and(|arg| arg == "{}") => {
+                    if predicate == "-ok" {
+                        return Err(ParseError(String::from(
+                            "the -ok action does not support `{} +'",
+                        )));
+                    }
+
+                    command.pop();
+                    let mode = ExecMode::Batch(self.batches);
+                    self.batches += 1;
+
+                    if command.is_empty() {
+                        return Err(ParseError(format!("missing argument to `{}'", predicate)));
+                    }
+
+                    return Ok(Expr::Action(Action::Exec(mode, command)));
+                }
+                Some(arg) => command.push(String::from(arg)),
+                None => return Err(ParseError(format!("missing argument to `{}'", predicate))),
+            }
+        }
+
+        if command.is_empty() {
+            return Err(ParseError(format!("missing argument to `{}'", predicate)));
+        }
+
+        let mode = match predicate {
+            "-ok" => ExecMode::Prompt,
+            _ => ExecMode::Each,
+        };
+
+        Ok(Expr::Action(Action::Exec(mode, command)))
+    }
+}
+
+pub fn is_expression_start(arg: &str) -> bool {
+    (arg.starts_with('-') && arg.len() > 1) || arg == "!" || arg == "("
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mode, Action, Comparison, ExecMode, Expr, Options, Test};
+
+    fn parse(args: &[&str]) -> Result<Expr, super::ParseError> {
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+        Expr::parse(&args).map(|(expr, _)| expr)
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = parse(&["-name", "a", "-o", "!", "-empty", "-type", "f", "-print"]).unwrap();
+
+        match expr {
+            Expr::Or(left, right) => {
+                assert!(matches!(*left, Expr::Test(Test::Name(_, true))));
+                match *right {
+                    Expr::And(left, right) => {
+                        assert!(matches!(*left, Expr::And(_, _)));
+                        assert!(matches!(*right, Expr::Action(Action::Print)));
+                    }
+                    other => panic!("expected an and expression, got {:?}", other),
+                }
+            }
+            other => panic!("expected an or expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_default_print() {
+        assert!(matches!(parse(&[]).unwrap(), Expr::Action(Action::Print)));
+
+        let expr = parse(&["-name", "a", "-o", "-name", "b"]).unwrap();
+        assert!(matches!(expr, Expr::And(_, right) if matches!(*right, Expr::Action(Action::Print))));
+
+        let expr = parse(&["-name", "a", "-exec", "rm", "{}", "+"]).unwrap();
+        match expr {
+            Expr::And(_, right) => match *right {
+                Expr::Action(Action::Exec(ExecMode::Batch(0), command)) => {
+                    assert_eq!(command, vec![String::from("rm")]);
+                }
+                other => panic!("expected a batched exec, got {:?}", other),
+            },
+            other => panic!("expected an and expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args: Vec<String> = ["-maxdepth", "2", "-mindepth", "1", "-xdev", "-prune"]
+            .iter()
+            .map(|arg| String::from(*arg))
+            .collect();
+        let (expr, options) = Expr::parse(&args).unwrap();
+        let Options {
+            max_depth,
+            min_depth,
+            depth_first,
+            same_file_system,
+        } = options;
+
+        assert_eq!(max_depth, Some(2));
+        assert_eq!(min_depth, 1);
+        assert!(!depth_first);
+        assert!(same_file_system);
+        assert!(matches!(expr, Expr::And(_, right) if matches!(*right, Expr::Action(Action::Print))));
+
+        let args = vec![String::from("-delete")];
+        let (_, options) = Expr::parse(&args).unwrap();
+        assert!(options.depth_first);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["-name"]).is_err());
+        assert!(parse(&["(", "-empty"]).is_err());
+        assert!(parse(&["-empty", ")"]).is_err());
+        assert!(parse(&["-o", "-empty"]).is_err());
+        assert!(parse(&["-size", "12q"]).is_err());
+        assert!(parse(&["-bogus"]).is_err());
+        assert!(parse(&["-exec", "rm", "{}"]).is_err());
+        assert!(parse(&["-ok", "rm", "{}", "+"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_comparison() {
+        assert_eq!(
+            Comparison::parse("+10k"),
+            Some((Comparison::GreaterThan(10), "k"))
+        );
+        assert_eq!(Comparison::parse("-3"), Some((Comparison::LessThan(3), "")));
+        assert_eq!(Comparison::parse("7"), Some((Comparison::Exactly(7), "")));
+        assert_eq!(Comparison::parse("+"), None);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("644"), Some(0o644));
+        assert_eq!(parse_mode("u+x"), Some(0o100));
+        assert_eq!(parse_mode("u=rw,go=r"), Some(0o644));
+        assert_eq!(parse_mode("a+rwx,o-w"), Some(0o775));
+        assert_eq!(parse_mode("u+q"), None);
+        assert_eq!(parse_mode("99"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+
+/// Answers whether a path is ignored by the `.ignore` and `.gitignore` files around it, the
+/// repository's `.git/info/exclude` and git's global excludes file, the way `git` and `fd` see
+/// it. The rules of each directory are read the first time something inside it is asked about.
+pub struct IgnoreRules {
+    cwd: PathBuf,
+    global: Gitignore,
+    dirs: Mutex<HashMap<PathBuf, Arc<DirRules>>>,
+}
+
+struct DirRules {
+    ignore: Option<Gitignore>,
+    gitignore: Option<Gitignore>,
+    exclude: Option<Gitignore>,
+    is_repo_root: bool,
+    in_repo: bool,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        Self {
+            cwd: env::current_dir().unwrap_or_default(),
+            global: Gitignore::global().0,
+            dirs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Deeper directories take precedence over shallower ones and, within a directory, `.ignore`
+    /// over `.gitignore`. Git's own rules stop at the repository root; `.ignore` files apply all
+    /// the way up.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        // Paths are made absolute so that every directory's rules can strip their own prefix.
+        let path: PathBuf = self.cwd.join(path).components().collect();
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+
+        let mut in_repo = true;
+        let mut repo_root = None;
+
+        for dir in path.ancestors().skip(1) {
+            let rules = self.rules(dir);
+
+            if let Some(ignored) = matched(rules.ignore.as_ref(), &path, is_dir) {
+                return ignored;
+            }
+
+            if in_repo && rules.in_repo {
+                if let Some(ignored) = matched(rules.gitignore.as_ref(), &path, is_dir) {
+                    return ignored;
+                }
+
+                if rules.is_repo_root {
+                    in_repo = false;
+                    repo_root = Some(rules);
+                }
+            }
+        }
+
+        match repo_root {
+            Some(rules) => matched(rules.exclude.as_ref(), &path, is_dir)
+                .or_else(|| matched(Some(&self.global), &path, is_dir))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    fn rules(&self, dir: &Path) -> Arc<DirRules> {
+        if let Some(rules) = self.dirs.lock().unwrap().get(dir) {
+            return Arc::clone(rules);
+        }
+
+        let parent_in_repo = dir.parent().is_some_and(|parent| self.rules(parent).in_repo);
+        let rules = Arc::new(DirRules::load(dir, parent_in_repo));
+
+        let mut dirs = self.dirs.lock().unwrap();
+        Arc::clone(dirs.entry(dir.to_path_buf()).or_insert(rules))
+    }
+}
+
+impl Default for IgnoreRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirRules {
+    fn load(dir: &Path, parent_in_repo: bool) -> Self {
+        let is_repo_root = dir.join(".git").exists();
+        let in_repo = is_repo_root || parent_in_repo;
+
+        Self {
+            ignore: load(dir, &dir.join(".ignore")),
+            gitignore: in_repo
+                .then(|| load(dir, &dir.join(".gitignore")))
+                .flatten(),
+            exclude: is_repo_root
+                .then(|| load(dir, &dir.join(".git").join("info").join("exclude")))
+                .flatten(),
+            is_repo_root,
+            in_repo,
+        }
+    }
+}
+
+/// Unreadable or malformed ignore files are skipped, as git does.
+fn load(dir: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    builder.add(file);
+
+    builder.build().ok()
+}
+
+fn matched(gitignore: Option<&Gitignore>, path: &Path, is_dir: bool) -> Option<bool> {
+    match gitignore?.matched(path, is_dir) {
+        Match::None => None,
+        Match::Ignore(_) => Some(true),
+        Match::Whitelist(_) => Some(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::IgnoreRules;
+
+    #[test]
+    fn test_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("repo/.git/info")).unwrap();
+        fs::create_dir_all(root.join("repo/src/generated")).unwrap();
+        fs::write(root.join("repo/.gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("repo/src/.gitignore"), "!keep.log\n").unwrap();
+        fs::write(root.join("repo/src/.ignore"), "generated/\n").unwrap();
+        fs::write(root.join("repo/.git/info/exclude"), "scratch\n").unwrap();
+        fs::write(root.join(".gitignore"), "*.rs\n").unwrap();
+
+        let rules = IgnoreRules::new();
+        let repo = root.join("repo");
+
+        assert!(rules.is_ignored(&repo.join("target"), true));
+        assert!(!rules.is_ignored(&repo.join("target"), false));
+        assert!(rules.is_ignored(&repo.join("src/debug.log"), false));
+        assert!(!rules.is_ignored(&repo.join("src/keep.log"), false));
+        assert!(rules.is_ignored(&repo.join("src/generated"), true));
+        assert!(rules.is_ignored(&repo.join("scratch"), false));
+        assert!(rules.is_ignored(&repo.join(".git"), true));
+        // The .gitignore above the repository isn't git's business.
+        assert!(!rules.is_ignored(&repo.join("src/main.rs"), false));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8] = b"\0find-index-1\n";
+
+/// Writes paths in the index format `updatedb` leaves for `locate`: after a magic line, each path
+/// is the number of leading bytes it shares with the one before it and the bytes that follow
+/// those, both lengths as LEB128 varints. Sorted paths mostly differ only in their last component,
+/// which keeps an index of a whole file system down to a fraction of its plain listing.
+pub fn write(out: &mut dyn Write, paths: &[Vec<u8>]) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+
+    let mut previous: &[u8] = &[];
+    for path in paths {
+        let shared = previous
+            .iter()
+            .zip(path)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        write_varint(out, shared)?;
+        write_varint(out, path.len() - shared)?;
+        out.write_all(&path[shared..])?;
+
+        previous = path;
+    }
+
+    Ok(())
+}
+
+/// An index read into memory in one go; decoding it is quicker than any walk could be.
+pub struct Index {
+    data: Vec<u8>,
+}
+
+impl Index {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        if !data.starts_with(MAGIC) {
+            return Err(Error::new(ErrorKind::InvalidData, "not a find index"));
+        }
+
+        Ok(Self { data })
+    }
+
+    /// Calls `f` with every path in the index, in order, until it returns `false`.
+    pub fn for_each(&self, mut f: impl FnMut(&[u8]) -> bool) -> io::Result<()> {
+        let mut data = &self.data[MAGIC.len()..];
+        let mut path = Vec::new();
+
+        while !data.is_empty() {
+            let shared = read_varint(&mut data)?;
+            let len = read_varint(&mut data)?;
+            if shared > path.len() || len > data.len() {
+                return Err(corrupt());
+            }
+
+            path.truncate(shared);
+            path.extend_from_slice(&data[..len]);
+            data = &data[len..];
+
+            if !f(&path) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_varint(out: &mut dyn Write, mut value: usize) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(data: &mut &[u8]) -> io::Result<usize> {
+    let mut value = 0usize;
+
+    for shift in (0..usize::BITS).step_by(7) {
+        let (&byte, rest) = data.split_first().ok_or_else(corrupt)?;
+        *data = rest;
+        value |= ((byte & 0x7f) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(corrupt())
+}
+
+fn corrupt() -> Error {
+    Error::new(ErrorKind::InvalidData, "the index is corrupt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write, Index, MAGIC};
+
+    #[test]
+    fn test_round_trip() {
+        let paths: Vec<Vec<u8>> = [
+            "/",
+            "/usr",
+            "/usr/lib",
+            "/usr/lib/libc.so",
+            "/usr/lib/libm.so",
+            "/usr/share",
+            "/\u{e9}t\u{e9}",
+        ]
+        .iter()
+        .map(|path| path.as_bytes().to_vec())
+        .chain([b"/x\xff".to_vec(), vec![b'/'; 300]])
+        .collect();
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("index");
+        let mut data = Vec::new();
+        write(&mut data, &paths).unwrap();
+        std::fs::write(&file, &data).unwrap();
+
+        let mut read = Vec::new();
+        Index::open(&file)
+            .unwrap()
+            .for_each(|path| {
+                read.push(path.to_vec());
+                true
+            })
+            .unwrap();
+        assert_eq!(read, paths);
+
+        std::fs::write(&file, [MAGIC, &[5, 1, b'a']].concat()).unwrap();
+        assert!(Index::open(&file).unwrap().for_each(|_| true).is_err());
+        std::fs::write(&file, b"plain text").unwrap();
+        assert!(Index::open(&file).is_err());
+    }
+}
@@ -1,56 +1,470 @@
-use std::{fmt::Display, fs, io::Result};
+use std::{
+    ffi::OsString,
+    io::{self, Error, ErrorKind, Result, Write},
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    time::SystemTime,
+};
 
+use action::Context;
 use clap::Parser;
+use duplicates::Duplicates;
+use expr::Expr;
+use watch::Watcher;
+
+pub use entry::Entry;
+pub use locate::{LocateCommand, UpdatedbCommand};
+pub use walk::{Filter, Follow, Walk, WalkError, Walker};
+
+mod action;
+mod duplicates;
+mod entry;
+mod expr;
+mod ignore_rules;
+mod index;
+mod locate;
+mod walk;
+mod watch;
 
 #[derive(Parser, Debug)]
 pub struct FindCommand {
-    #[arg(value_name = "[path...]", default_value = ".")]
-    path: String,
+    #[arg(
+        short = 'P',
+        help = "never follow symbolic links (default)",
+        overrides_with_all = ["follow_root_links", "follow_links"]
+    )]
+    never_follow_links: bool,
+
+    #[arg(
+        short = 'H',
+        help = "follow symbolic links given on the command line only",
+        overrides_with_all = ["never_follow_links", "follow_links"]
+    )]
+    follow_root_links: bool,
+
+    #[arg(
+        short = 'L',
+        help = "follow all symbolic links",
+        overrides_with_all = ["never_follow_links", "follow_root_links"]
+    )]
+    follow_links: bool,
+
+    #[arg(
+        long = "sort",
+        help = "visit the entries of each directory in name order, so the output is the same on every run"
+    )]
+    sort: bool,
+
+    #[arg(
+        long = "threads",
+        value_name = "N",
+        help = "read directories on N threads; entries come out in no particular order unless --sort is given"
+    )]
+    threads: Option<usize>,
+
+    #[arg(
+        long = "respect-gitignore",
+        help = "skip whatever .gitignore, .ignore and git's exclude files ignore, and .git itself"
+    )]
+    respect_gitignore: bool,
+
+    #[arg(
+        long = "hidden",
+        help = "include entries whose names start with a dot (default)",
+        overrides_with = "no_hidden"
+    )]
+    hidden: bool,
+
+    #[arg(
+        long = "no-hidden",
+        help = "skip entries whose names start with a dot",
+        overrides_with = "hidden"
+    )]
+    no_hidden: bool,
+
+    #[arg(
+        long = "duplicates",
+        help = "list sets of files with the same contents among the regular files the expression matches, instead of the matches themselves"
+    )]
+    duplicates: bool,
+
+    #[arg(
+        long = "all-links",
+        requires = "duplicates",
+        help = "with --duplicates, list every hard link to a file and count hard links to one file as duplicates of each other"
+    )]
+    all_links: bool,
+
+    #[arg(
+        long = "hardlink",
+        requires = "duplicates",
+        help = "with --duplicates, replace every file in a set with a hard link to the first one"
+    )]
+    hardlink: bool,
+
+    #[arg(
+        long = "watch",
+        conflicts_with = "duplicates",
+        help = "after listing the matches, keep reporting matching entries as they are created, modified, deleted or renamed"
+    )]
+    watch: bool,
+
+    #[arg(
+        long = "json",
+        requires = "watch",
+        help = "with --watch, report each change as a line of JSON"
+    )]
+    json: bool,
+
+    #[arg(
+        value_name = "[path...] [expression]",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    args: Vec<OsString>,
 }
 
 impl FindCommand {
-    pub fn exec(&self) -> Result<FindCommandResult> {
-        let mut entries = Vec::new();
-        Self::traverse_and_add(&mut entries, self.path.clone())?;
+    pub fn exec(&self, out: &mut dyn Write) -> Result<FindCommandResult> {
+        stop_at_broken_pipe(self.exec_to(out))
+    }
+
+    fn exec_to(&self, out: &mut dyn Write) -> Result<FindCommandResult> {
+        if self.duplicates {
+            return self.exec_duplicates(out);
+        }
+        if self.watch {
+            return self.exec_watch(out);
+        }
+
+        let (walker, expr, depth_first) = self.prepare()?;
+        let mut ctx = Context::new(SystemTime::now(), out);
+        run(walker.build(), &expr, depth_first, &mut ctx, |_, _, _| {})?;
+        ctx.finish()?;
+
+        Ok(FindCommandResult::new(ctx.failed()))
+    }
+
+    /// Runs the walk for the entries the expression matches, rather than for what it prints. The
+    /// output is thrown away, so `mode` is refused outright for actions that do more than print.
+    /// Returns whether anything went wrong along the way.
+    pub(crate) fn select(&self, mode: &str, mut matched: impl FnMut(&Entry)) -> Result<bool> {
+        let (walker, expr, depth_first) = self.prepare()?;
+        refuse_side_effects(&expr, mode)?;
+
+        let mut sink = io::sink();
+        let mut ctx = Context::new(SystemTime::now(), &mut sink);
+        run(walker.build(), &expr, depth_first, &mut ctx, |entry, is_match, _| {
+            if is_match {
+                matched(entry);
+            }
+        })?;
+
+        Ok(ctx.failed())
+    }
+
+    /// Lists what the expression matches as usual, then keeps watching every directory the walk
+    /// went into and reports the changes to matching entries until it is interrupted.
+    fn exec_watch(&self, out: &mut dyn Write) -> Result<FindCommandResult> {
+        let (walker, expr, depth_first) = self.prepare()?;
+        refuse_side_effects(&expr, "--watch")?;
+
+        let (roots, options) = walker.into_parts();
+        let mut watcher = Watcher::new(&expr, options.clone(), self.json)?;
+
+        let mut ctx = Context::new(SystemTime::now(), out);
+        run(
+            Walk::new(roots, options),
+            &expr,
+            depth_first,
+            &mut ctx,
+            |entry, matched, pruned| watcher.visit(entry, matched, pruned),
+        )?;
+        ctx.finish()?;
+        let failed = ctx.failed();
+
+        watcher.watch(out)?;
+
+        Ok(FindCommandResult::new(failed || watcher.failed()))
+    }
+
+    fn prepare(&self) -> Result<(Walker, Expr, bool)> {
+        let (roots, expression) = self.roots_and_expression()?;
+        let (expr, options) =
+            Expr::parse(&expression).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        let mut walker = roots
+            .iter()
+            .skip(1)
+            .fold(Walker::new(&roots[0]), |walker, root| walker.root(root))
+            .min_depth(options.min_depth)
+            .follow(self.follow())
+            .contents_first(options.depth_first)
+            .same_file_system(options.same_file_system)
+            .sort(self.sort)
+            .threads(self.threads.unwrap_or(1))
+            .hidden(!self.no_hidden)
+            .respect_ignore_files(self.respect_gitignore);
+        if let Some(max_depth) = options.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+
+        Ok((walker, expr, options.depth_first))
+    }
+
+    fn exec_duplicates(&self, out: &mut dyn Write) -> Result<FindCommandResult> {
+        let mut duplicates = Duplicates::new();
+        let mut failed = self.select("--duplicates", |entry| duplicates.add(entry))?;
+
+        let sets = duplicates.sets(self.all_links, &mut |path, err| {
+            eprintln!("find: '{}': {}", path.display(), io_error_message(&err));
+            failed = true;
+        });
+
+        for (idx, set) in sets.iter().enumerate() {
+            if idx > 0 {
+                out.write_all(b"\n")?;
+            }
+
+            for inode in set {
+                let paths = if self.all_links {
+                    inode.paths()
+                } else {
+                    &inode.paths()[..1]
+                };
+
+                for path in paths {
+                    out.write_all(path.as_os_str().as_bytes())?;
+                    out.write_all(b"\n")?;
+                }
+            }
+
+            if self.hardlink {
+                for (path, err) in duplicates::hardlink(set) {
+                    eprintln!("find: '{}': {}", path.display(), io_error_message(&err));
+                    failed = true;
+                }
+            }
+        }
 
-        Ok(FindCommandResult::new(entries))
+        out.flush()?;
+
+        Ok(FindCommandResult::new(failed))
+    }
+
+    fn follow(&self) -> Follow {
+        if self.follow_links {
+            Follow::Always
+        } else if self.follow_root_links {
+            Follow::Roots
+        } else {
+            Follow::Never
+        }
+    }
+
+    /// Like find, everything up to the first argument that looks like part of an expression is a
+    /// starting point, and the walk starts from `.` when there are none.
+    fn roots_and_expression(&self) -> Result<(Vec<PathBuf>, Vec<String>)> {
+        let split = self
+            .args
+            .iter()
+            .position(|arg| arg.to_str().is_some_and(expr::is_expression_start))
+            .unwrap_or(self.args.len());
+
+        let mut roots: Vec<PathBuf> = self.args[..split].iter().map(PathBuf::from).collect();
+        if roots.is_empty() {
+            roots.push(PathBuf::from("."));
+        }
+
+        let expression = self.args[split..]
+            .iter()
+            .map(|arg| {
+                arg.clone().into_string().map_err(|arg| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid argument '{}'", arg.to_string_lossy()),
+                    )
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((roots, expression))
+    }
+}
+
+/// The expression only picks entries out for modes that don't print them as they go, so it
+/// can't be allowed to do anything else to them.
+fn refuse_side_effects(expr: &Expr, mode: &str) -> Result<()> {
+    if expr.has_side_effects() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} can't be combined with -delete or -exec", mode),
+        ));
     }
 
-    fn traverse_and_add(entries: &mut Vec<String>, path: String) -> Result<()> {
-        entries.push(path.clone());
+    Ok(())
+}
+
+/// Feeds every entry of the walk to the expression, then to `visit` along with whether the
+/// expression held for it and whether it pruned it.
+fn run(
+    mut walk: Walk,
+    expr: &Expr,
+    depth_first: bool,
+    ctx: &mut Context,
+    mut visit: impl FnMut(&Entry, bool, bool),
+) -> Result<()> {
+    while let Some(result) = walk.next() {
+        match result {
+            Ok(entry) => {
+                let matched = expr.evaluate(&entry, ctx);
+                ctx.take_error()?;
 
-        for dir_entry in fs::read_dir(path)? {
-            let dir_entry = dir_entry?;
-            let file_type = dir_entry.file_type()?;
-            let entry_path = dir_entry
-                .path()
-                .into_os_string()
-                .into_string()
-                .expect("Should parse to string");
+                let pruned = ctx.take_pruned() && !depth_first;
+                if pruned {
+                    walk.skip_current_dir();
+                }
 
-            if file_type.is_dir() {
-                Self::traverse_and_add(entries, entry_path)?;
-            } else {
-                entries.push(entry_path);
+                visit(&entry, matched, pruned);
+            }
+            Err(err) => {
+                eprintln!("find: {}", err);
+                ctx.fail();
             }
         }
+    }
+
+    Ok(())
+}
 
-        Ok(())
+/// Like find, stops quietly when whatever reads the output goes away, as with `find / | head`,
+/// rather than reporting the broken pipe.
+pub(crate) fn stop_at_broken_pipe(result: Result<FindCommandResult>) -> Result<FindCommandResult> {
+    match result {
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(FindCommandResult::new(false)),
+        result => result,
     }
 }
 
 pub struct FindCommandResult {
-    entries: Vec<String>,
+    failed: bool,
 }
 
 impl FindCommandResult {
-    pub fn new(entries: Vec<String>) -> Self {
-        Self { entries }
+    pub fn new(failed: bool) -> Self {
+        Self { failed }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.failed {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// `io::Error`'s `Display` appends "(os error N)", which find's messages never carry.
+pub(crate) fn io_error_message(err: &io::Error) -> String {
+    let message = err.to_string();
+
+    match message.find(" (os error ") {
+        Some(idx) => String::from(&message[..idx]),
+        None => message,
     }
 }
 
-impl Display for FindCommandResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.entries.join("\n"))
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::OsStr,
+        fs,
+        io::{self, ErrorKind, Write},
+        os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    };
+
+    use clap::Parser;
+
+    use super::FindCommand;
+
+    /// A pipe whose reader is gone.
+    pub(crate) struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn test_exec_broken_pipe() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "").unwrap();
+
+        let cmd = FindCommand::try_parse_from(["find".as_ref(), dir.path().as_os_str()]).unwrap();
+        let result = cmd.exec(&mut ClosedPipe).unwrap();
+        assert_eq!(result.exit_code(), 0);
+
+        let cmd = FindCommand::try_parse_from(["find", "/nonexistent", "-printf", "%p"]).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(cmd.exec(&mut out).unwrap().exit_code(), 1);
+    }
+
+    #[test]
+    fn test_exec_unreadable_dir() {
+        // root reads the directory regardless
+        if users::get_effective_uid() == 0 {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("open")).unwrap();
+        fs::create_dir(dir.path().join("locked")).unwrap();
+        fs::write(dir.path().join("locked/hidden"), "").unwrap();
+        fs::write(dir.path().join("open/file"), "").unwrap();
+        fs::set_permissions(dir.path().join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        let cmd = FindCommand::try_parse_from([
+            "find".as_ref(),
+            dir.path().as_os_str(),
+            "-printf".as_ref(),
+            "%P\\n".as_ref(),
+        ])
+        .unwrap();
+        let mut out = Vec::new();
+        let result = cmd.exec(&mut out).unwrap();
+        fs::set_permissions(dir.path().join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        // the directory itself is still listed and the walk goes on past it, but find fails
+        let mut lines: Vec<_> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, ["", "locked", "open", "open/file"]);
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[test]
+    fn test_exec_non_utf8_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = OsStr::from_bytes(b"caf\xff");
+        fs::write(dir.path().join(name), "").unwrap();
+
+        for args in [["-name", "caf?"], ["-regex", ".*caf.*"]] {
+            let mut cmd_args = vec!["find".as_ref(), dir.path().as_os_str()];
+            cmd_args.extend(args.map(OsStr::new));
+            cmd_args.extend(["-printf", "%f"].map(OsStr::new));
+
+            let cmd = FindCommand::try_parse_from(cmd_args).unwrap();
+            let mut out = Vec::new();
+            assert_eq!(cmd.exec(&mut out).unwrap().exit_code(), 0);
+            assert_eq!(out, name.as_bytes(), "with {:?}", args);
+        }
     }
 }
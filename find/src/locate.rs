@@ -0,0 +1,390 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufWriter, Error, ErrorKind, Result, Write},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use glob::Pattern;
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::{expr, index, stop_at_broken_pipe, FindCommand, FindCommandResult};
+
+/// find's options that don't build an index are hidden from updatedb's help.
+const FIND_ONLY_ARGS: [&str; 5] = ["duplicates", "all_links", "hardlink", "watch", "json"];
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "updatedb",
+    mut_args = |arg| match FIND_ONLY_ARGS.contains(&arg.get_id().as_str()) {
+        true => arg.hide(true),
+        false => arg,
+    }
+)]
+pub struct UpdatedbCommand {
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "FILE",
+        help = "write the index to FILE instead of the default one"
+    )]
+    output: Option<PathBuf>,
+
+    /// Everything find takes: which paths to index and an expression picking the entries that
+    /// go in.
+    #[command(flatten)]
+    find: FindCommand,
+}
+
+impl UpdatedbCommand {
+    /// Paths are stored absolute so that the index answers the same wherever `locate` runs.
+    pub fn exec(&self) -> Result<FindCommandResult> {
+        if self.find.duplicates || self.find.watch {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--duplicates and --watch can't be used to build an index",
+            ));
+        }
+
+        let cwd = env::current_dir()?;
+        let mut paths = Vec::new();
+        let failed = self.find.select("updatedb", |entry| {
+            let path: PathBuf = cwd.join(entry.path()).components().collect();
+            paths.push(path.into_os_string().into_vec());
+        })?;
+
+        paths.sort_unstable();
+        paths.dedup();
+
+        let output = match &self.output {
+            Some(output) => output.clone(),
+            None => default_database()?,
+        };
+        if let Some(parent) = output.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        // A `locate` running meanwhile keeps seeing the old index until the new one is complete.
+        let mut tmp = output.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        index::write(&mut out, &paths)?;
+        out.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+        fs::rename(&tmp, &output)?;
+
+        Ok(FindCommandResult::new(failed))
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "locate")]
+pub struct LocateCommand {
+    #[arg(
+        short = 'd',
+        long = "database",
+        value_name = "FILE",
+        help = "search the index in FILE instead of the default one"
+    )]
+    database: Option<PathBuf>,
+
+    #[arg(short = 'i', long = "ignore-case", help = "ignore case when matching")]
+    ignore_case: bool,
+
+    #[arg(
+        short = 'b',
+        long = "basename",
+        help = "match against the last component of each path only"
+    )]
+    basename: bool,
+
+    #[arg(
+        short = 'r',
+        long = "regex",
+        help = "take the patterns as regular expressions"
+    )]
+    regex: bool,
+
+    #[arg(
+        short = 'A',
+        long = "all",
+        help = "list paths matching every pattern rather than any of them"
+    )]
+    all: bool,
+
+    #[arg(
+        short = 'e',
+        long = "existing",
+        help = "leave out paths that no longer exist"
+    )]
+    existing: bool,
+
+    #[arg(
+        short = 'c',
+        long = "count",
+        help = "print how many paths match instead of the paths"
+    )]
+    count: bool,
+
+    #[arg(
+        short = 'l',
+        long = "limit",
+        value_name = "N",
+        help = "stop after N matches"
+    )]
+    limit: Option<usize>,
+
+    #[arg(
+        short = '0',
+        long = "null",
+        help = "end each path with a null character instead of a newline"
+    )]
+    null: bool,
+
+    #[arg(
+        value_name = "PATTERN",
+        required = true,
+        help = "a substring to look for, or, when it has any of '*?[', a glob the whole path (or name, with -b) has to match"
+    )]
+    patterns: Vec<String>,
+}
+
+enum Matcher {
+    Regex(Regex),
+    Glob(Pattern),
+}
+
+impl LocateCommand {
+    /// Like locate, finding nothing is a failure.
+    pub fn exec(&self, out: &mut dyn Write) -> Result<FindCommandResult> {
+        stop_at_broken_pipe(self.exec_to(out))
+    }
+
+    fn exec_to(&self, out: &mut dyn Write) -> Result<FindCommandResult> {
+        let matchers = self
+            .patterns
+            .iter()
+            .map(|pattern| self.matcher(pattern))
+            .collect::<Result<Vec<_>>>()?;
+
+        let database = match &self.database {
+            Some(database) => database.clone(),
+            None => default_database()?,
+        };
+        let index = index::Index::open(&database).map_err(|err| {
+            Error::new(
+                err.kind(),
+                format!("'{}': {}", database.display(), crate::io_error_message(&err)),
+            )
+        })?;
+
+        let mut count = 0;
+        let mut error = None;
+        index.for_each(|path| {
+            if !self.matches(&matchers, path)
+                || (self.existing && fs::symlink_metadata(OsStr::from_bytes(path)).is_err())
+            {
+                return true;
+            }
+            if self.limit.is_some_and(|limit| count >= limit) {
+                return false;
+            }
+
+            count += 1;
+            if !self.count {
+                let terminator: &[u8] = if self.null { b"\0" } else { b"\n" };
+                if let Err(err) = out.write_all(path).and_then(|_| out.write_all(terminator)) {
+                    error = Some(err);
+                    return false;
+                }
+            }
+
+            true
+        })?;
+
+        if let Some(err) = error {
+            return Err(err);
+        }
+        if self.count {
+            writeln!(out, "{}", count)?;
+        }
+        out.flush()?;
+
+        Ok(FindCommandResult::new(count == 0))
+    }
+
+    fn matcher(&self, pattern: &str) -> Result<Matcher> {
+        let invalid = |err: String| Error::new(ErrorKind::InvalidInput, err);
+
+        if !self.regex && pattern.contains(['*', '?', '[']) {
+            return Pattern::new(pattern)
+                .map(Matcher::Glob)
+                .map_err(|err| invalid(format!("invalid pattern '{}': {}", pattern, err)));
+        }
+
+        let regex = if self.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+
+        RegexBuilder::new(&regex)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map(Matcher::Regex)
+            .map_err(|err| invalid(format!("invalid regular expression: {}", err)))
+    }
+
+    fn matches(&self, matchers: &[Matcher], path: &[u8]) -> bool {
+        let subject = if self.basename {
+            Path::new(OsStr::from_bytes(path))
+                .file_name()
+                .map_or(path, |name| name.as_bytes())
+        } else {
+            path
+        };
+
+        let matches = |matcher: &Matcher| match matcher {
+            Matcher::Regex(regex) => regex.is_match(subject),
+            Matcher::Glob(pattern) => pattern.matches_with(
+                &String::from_utf8_lossy(subject),
+                expr::match_options(!self.ignore_case),
+            ),
+        };
+
+        if self.all {
+            matchers.iter().all(matches)
+        } else {
+            matchers.iter().any(matches)
+        }
+    }
+}
+
+/// `$XDG_CACHE_HOME/find/locate.db`, falling back to `~/.cache` like everything else does.
+fn default_database() -> Result<PathBuf> {
+    let cache = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(".cache"),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    "no index given and neither XDG_CACHE_HOME nor HOME is set",
+                ))
+            }
+        },
+    };
+
+    Ok(cache.join("find").join("locate.db"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        path::Path,
+    };
+
+    use clap::{CommandFactory, Parser};
+
+    use super::{LocateCommand, UpdatedbCommand};
+    use crate::{index, tests::ClosedPipe};
+
+    /// Runs locate with `args` against `database`, and returns what it printed with `root` left
+    /// out of the paths, and its exit code.
+    fn locate(database: &Path, root: &Path, args: &[&str]) -> (String, i32) {
+        let mut argv = vec!["locate", "-d", database.to_str().unwrap()];
+        argv.extend(args);
+        let cmd = LocateCommand::try_parse_from(argv).unwrap();
+
+        let mut out = Vec::new();
+        let result = cmd.exec(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        (out.replace(root.to_str().unwrap(), ""), result.exit_code())
+    }
+
+    #[test]
+    fn test_updatedb_and_locate() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        for path in ["docs", "lib", "src"] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        for path in ["docs/README.md", "lib/libc.so", "src/lib.rs", "src/main.rs"] {
+            fs::write(root.join(path), "").unwrap();
+        }
+
+        let database = dir.path().join("locate.db");
+        let cmd = UpdatedbCommand::try_parse_from([
+            "updatedb".as_ref(),
+            "-o".as_ref(),
+            database.as_os_str(),
+            root.as_os_str(),
+        ])
+        .unwrap();
+        assert_eq!(cmd.exec().unwrap().exit_code(), 0);
+        fs::remove_file(root.join("src/main.rs")).unwrap();
+
+        let locate = |args: &[&str]| locate(&database, &root, args);
+        let found = |lines: &str| (lines.to_string(), 0);
+
+        assert_eq!(locate(&["lib"]), found("/lib\n/lib/libc.so\n/src/lib.rs\n"));
+        assert_eq!(locate(&["*.rs"]), found("/src/lib.rs\n/src/main.rs\n"));
+        assert_eq!(
+            locate(&["-r", r"lib.*\.(rs|so)$"]),
+            found("/lib/libc.so\n/src/lib.rs\n")
+        );
+        assert_eq!(locate(&["-b", "src"]), found("/src\n"));
+        assert_eq!(
+            locate(&["-b", "lib*"]),
+            found("/lib\n/lib/libc.so\n/src/lib.rs\n")
+        );
+        assert_eq!(locate(&["readme"]), (String::new(), 1));
+        assert_eq!(locate(&["-i", "readme"]), found("/docs/README.md\n"));
+        assert_eq!(
+            locate(&["src", "so"]),
+            found("/lib/libc.so\n/src\n/src/lib.rs\n/src/main.rs\n")
+        );
+        assert_eq!(
+            locate(&["-A", "src", "rs"]),
+            found("/src/lib.rs\n/src/main.rs\n")
+        );
+        assert_eq!(locate(&["-e", "rs"]), found("/src/lib.rs\n"));
+        assert_eq!(locate(&["-e", "main"]), (String::new(), 1));
+        assert_eq!(locate(&["-c", "lib"]), found("3\n"));
+        assert_eq!(
+            locate(&["-0", "lib"]),
+            found("/lib\0/lib/libc.so\0/src/lib.rs\0")
+        );
+        assert_eq!(locate(&["-l", "2", "lib"]), found("/lib\n/lib/libc.so\n"));
+        assert_eq!(locate(&["-c", "-l", "2", "lib"]), found("2\n"));
+        assert_eq!(locate(&["-l", "0", "lib"]), (String::new(), 1));
+    }
+
+    #[test]
+    fn test_locate_broken_pipe() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("locate.db");
+        let paths = [b"/usr/lib".to_vec(), b"/usr/lib/libc.so".to_vec()];
+        let mut file = File::create(&database).unwrap();
+        index::write(&mut file, &paths).unwrap();
+
+        let database = database.to_str().unwrap();
+        let cmd = LocateCommand::try_parse_from(["locate", "-d", database, "lib"]).unwrap();
+        assert_eq!(cmd.exec(&mut ClosedPipe).unwrap().exit_code(), 0);
+    }
+
+    #[test]
+    fn test_updatedb_help() {
+        let help = UpdatedbCommand::command().render_help().to_string();
+
+        assert!(help.contains("--output"));
+        assert!(help.contains("--sort"));
+        for arg in ["--duplicates", "--all-links", "--hardlink", "--watch", "--json"] {
+            assert!(!help.contains(arg), "{} is offered", arg);
+        }
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    io::{self, BufWriter},
+    process,
+};
+
 use clap::Parser;
 use color_eyre::Result;
 use find::FindCommand;
@@ -6,9 +11,8 @@ fn main() -> Result<()> {
     color_eyre::install()?;
 
     let cmd = FindCommand::parse();
-    let result = cmd.exec()?;
-
-    println!("{}", result);
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = cmd.exec(&mut out)?;
 
-    Ok(())
+    process::exit(result.exit_code());
 }
@@ -0,0 +1,1043 @@
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    error::Error,
+    fmt::Display,
+    fs::{self, Metadata, ReadDir},
+    io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+    thread, vec,
+};
+
+use crate::{entry::Entry, ignore_rules::IgnoreRules, io_error_message};
+
+/// How many entries worker threads may get ahead of whoever consumes the walk.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Follow {
+    #[default]
+    Never,
+    Roots,
+    Always,
+}
+
+/// Decides whether an entry is part of the walk at all; a rejected directory isn't read either.
+pub type Filter = Arc<dyn Fn(&Entry) -> bool + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct WalkOptions {
+    pub follow: Follow,
+    pub max_depth: Option<usize>,
+    pub min_depth: usize,
+    pub contents_first: bool,
+    pub same_file_system: bool,
+    pub sort: bool,
+    /// Anything above one reads directories on that many threads. A contents-first walk needs
+    /// every directory to be finished before it is reported, so it always runs on one thread.
+    pub threads: usize,
+    pub skip_hidden: bool,
+    pub respect_ignore_files: bool,
+    pub filters: Vec<Filter>,
+}
+
+#[derive(Debug)]
+pub enum WalkError {
+    Io(PathBuf, io::Error),
+    Loop(PathBuf, PathBuf),
+}
+
+impl WalkError {
+    pub fn path(&self) -> &Path {
+        match self {
+            WalkError::Io(path, _) | WalkError::Loop(path, _) => path,
+        }
+    }
+}
+
+impl Display for WalkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalkError::Io(path, err) => {
+                write!(f, "'{}': {}", path.display(), io_error_message(err))
+            }
+            WalkError::Loop(path, ancestor) => write!(
+                f,
+                "File system loop detected; '{}' is part of the same file system loop as '{}'.",
+                path.display(),
+                ancestor.display()
+            ),
+        }
+    }
+}
+
+impl Error for WalkError {}
+
+type WalkResult = Result<Entry, WalkError>;
+
+impl WalkOptions {
+    /// Broken symbolic links are still reported, as the links themselves, when following.
+    pub(crate) fn metadata(&self, path: &Path, depth: usize) -> io::Result<Metadata> {
+        let follow = match self.follow {
+            Follow::Never => false,
+            Follow::Roots => depth == 0,
+            Follow::Always => true,
+        };
+
+        if follow {
+            fs::metadata(path).or_else(|_| fs::symlink_metadata(path))
+        } else {
+            fs::symlink_metadata(path)
+        }
+    }
+
+    pub(crate) fn descends(&self, metadata: &Metadata, depth: usize, root_device: u64) -> bool {
+        metadata.is_dir()
+            && self.max_depth.is_none_or(|max| depth < max)
+            && !(self.same_file_system && metadata.dev() != root_device)
+    }
+
+    pub(crate) fn in_range(&self, depth: usize) -> bool {
+        depth >= self.min_depth
+    }
+
+    pub(crate) fn accepts(&self, entry: &Entry) -> bool {
+        self.filters.iter().all(|filter| filter(entry))
+    }
+}
+
+/// Builds a [`Walk`] over one or more roots.
+///
+/// ```no_run
+/// use find::{Follow, Walker};
+///
+/// let walk = Walker::new("src")
+///     .root("tests")
+///     .max_depth(2)
+///     .follow(Follow::Roots)
+///     .filter(|entry| entry.file_name() != "target")
+///     .build();
+///
+/// for entry in walk.flatten() {
+///     println!("{} {}", entry.path().display(), entry.metadata().len());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Walker {
+    roots: Vec<PathBuf>,
+    options: WalkOptions,
+}
+
+impl Walker {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            roots: vec![root.as_ref().to_path_buf()],
+            options: WalkOptions {
+                threads: 1,
+                ..WalkOptions::default()
+            },
+        }
+    }
+
+    pub fn root<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self
+    }
+
+    pub fn follow(mut self, follow: Follow) -> Self {
+        self.options.follow = follow;
+        self
+    }
+
+    /// Returns directories after everything inside them instead of before.
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.options.contents_first = contents_first;
+        self
+    }
+
+    pub fn same_file_system(mut self, same_file_system: bool) -> Self {
+        self.options.same_file_system = same_file_system;
+        self
+    }
+
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.options.sort = sort;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self
+    }
+
+    /// Whether entries whose names start with a dot are part of the walk; they are by default.
+    /// Roots are always walked, whatever their names.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.options.skip_hidden = !hidden;
+        self
+    }
+
+    /// Leaves out whatever `.gitignore`, `.ignore`, `.git/info/exclude` and git's global excludes
+    /// file would ignore, along with `.git` directories themselves.
+    pub fn respect_ignore_files(mut self, respect: bool) -> Self {
+        self.options.respect_ignore_files = respect;
+        self
+    }
+
+    /// Can be called several times; an entry has to pass every filter.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Entry) -> bool + Send + Sync + 'static,
+    {
+        self.options.filters.push(Arc::new(filter));
+        self
+    }
+
+    pub fn build(self) -> Walk {
+        let (roots, options) = self.into_parts();
+
+        Walk::new(roots, options)
+    }
+
+    /// The roots, and the options the walk runs with once every filter is in place.
+    pub(crate) fn into_parts(mut self) -> (Vec<PathBuf>, WalkOptions) {
+        if self.options.skip_hidden {
+            self = self.filter(|entry| {
+                entry.depth() == 0 || !entry.file_name().as_bytes().starts_with(b".")
+            });
+        }
+
+        if self.options.respect_ignore_files {
+            let rules = IgnoreRules::new();
+            self = self.filter(move |entry| {
+                entry.depth() == 0 || !rules.is_ignored(entry.path(), entry.file_type().is_dir())
+            });
+        }
+
+        (self.roots, self.options)
+    }
+}
+
+/// Walks the roots one entry at a time. Directories are read only once the walk gets to them, so
+/// entries come out while the rest of the tree is still unexplored.
+pub struct Walk {
+    inner: Inner,
+}
+
+enum Inner {
+    Sequential(Box<Sequential>),
+    Parallel(Parallel),
+}
+
+impl Walk {
+    pub(crate) fn new(roots: Vec<PathBuf>, options: WalkOptions) -> Self {
+        let inner = if options.threads > 1 && !options.contents_first {
+            Inner::Parallel(Parallel::new(roots, options))
+        } else {
+            Inner::Sequential(Box::new(Sequential::new(roots, options)))
+        };
+
+        Self { inner }
+    }
+
+    /// Stops the walk from going into the directory it has just returned.
+    pub fn skip_current_dir(&mut self) {
+        match &mut self.inner {
+            Inner::Sequential(walk) => walk.skip = true,
+            Inner::Parallel(walk) => {
+                if let Some(dir) = walk.last_dir.take() {
+                    walk.skipped.insert(dir);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = WalkResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            Inner::Sequential(walk) => walk.next(),
+            Inner::Parallel(walk) => walk.next(),
+        }
+    }
+}
+
+struct Sequential {
+    options: WalkOptions,
+    roots: vec::IntoIter<PathBuf>,
+    root: Arc<Path>,
+    root_device: u64,
+    /// Directories currently being read, innermost last. Keeping them here instead of on the call
+    /// stack means deep trees can't overflow it.
+    stack: Vec<Frame>,
+    /// A directory that was just returned and will be read on the next call, unless skipped.
+    pending: Option<Pending>,
+    skip: bool,
+}
+
+struct Pending {
+    path: PathBuf,
+    depth: usize,
+    device: u64,
+    inode: u64,
+    /// The directory's own entry when it has to wait for its contents (contents-first walks).
+    entry: Option<Entry>,
+}
+
+struct Frame {
+    path: PathBuf,
+    depth: usize,
+    device: u64,
+    inode: u64,
+    children: Children,
+    entry: Option<Entry>,
+}
+
+enum Children {
+    Streaming(ReadDir),
+    Sorted(vec::IntoIter<io::Result<PathBuf>>),
+    Empty,
+}
+
+impl Children {
+    fn new(dir: ReadDir, sort: bool) -> Self {
+        if !sort {
+            return Children::Streaming(dir);
+        }
+
+        let mut paths: Vec<_> = dir
+            .map(|dir_entry| dir_entry.map(|dir_entry| dir_entry.path()))
+            .collect();
+        paths.sort_by(|a, b| match (a, b) {
+            (Ok(a), Ok(b)) => a.file_name().cmp(&b.file_name()),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => Ordering::Equal,
+        });
+
+        Children::Sorted(paths.into_iter())
+    }
+}
+
+impl Iterator for Children {
+    type Item = io::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Children::Streaming(dir) => dir
+                .next()
+                .map(|dir_entry| dir_entry.map(|dir_entry| dir_entry.path())),
+            Children::Sorted(paths) => paths.next(),
+            Children::Empty => None,
+        }
+    }
+}
+
+impl Sequential {
+    fn new(roots: Vec<PathBuf>, options: WalkOptions) -> Self {
+        Self {
+            options,
+            roots: roots.into_iter(),
+            root: Arc::from(Path::new("")),
+            root_device: 0,
+            stack: Vec::new(),
+            pending: None,
+            skip: false,
+        }
+    }
+
+    fn open(&mut self, pending: Pending) -> Result<(), WalkError> {
+        let Pending {
+            path,
+            depth,
+            device,
+            inode,
+            entry,
+        } = pending;
+
+        let ancestor = self
+            .stack
+            .iter()
+            .find(|frame| frame.device == device && frame.inode == inode)
+            .map(|frame| frame.path.clone());
+
+        let (children, result) = match ancestor {
+            Some(ancestor) => (Children::Empty, Err(WalkError::Loop(path.clone(), ancestor))),
+            None => match fs::read_dir(&path) {
+                Ok(dir) => (Children::new(dir, self.options.sort), Ok(())),
+                Err(err) => (Children::Empty, Err(WalkError::Io(path.clone(), err))),
+            },
+        };
+
+        self.stack.push(Frame {
+            path,
+            depth,
+            device,
+            inode,
+            children,
+            entry,
+        });
+
+        result
+    }
+}
+
+impl Iterator for Sequential {
+    type Item = WalkResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pending) = self.pending.take() {
+                if !std::mem::take(&mut self.skip) {
+                    if let Err(err) = self.open(pending) {
+                        return Some(Err(err));
+                    }
+                }
+            }
+            self.skip = false;
+
+            let (path, depth) = match self.stack.last_mut() {
+                Some(frame) => match frame.children.next() {
+                    Some(Ok(path)) => (path, frame.depth + 1),
+                    Some(Err(err)) => return Some(Err(WalkError::Io(frame.path.clone(), err))),
+                    None => {
+                        if let Some(entry) = self.stack.pop().and_then(|frame| frame.entry) {
+                            return Some(Ok(entry));
+                        }
+
+                        continue;
+                    }
+                },
+                None => {
+                    let root = self.roots.next()?;
+                    self.root = Arc::from(root.as_path());
+
+                    (root, 0)
+                }
+            };
+
+            let metadata = match self.options.metadata(&path, depth) {
+                Ok(metadata) => metadata,
+                Err(err) => return Some(Err(WalkError::Io(path, err))),
+            };
+            if depth == 0 {
+                self.root_device = metadata.dev();
+            }
+
+            let descend = self.options.descends(&metadata, depth, self.root_device);
+            let in_range = self.options.in_range(depth);
+            let (device, inode) = (metadata.dev(), metadata.ino());
+            let entry = Entry::new(Arc::clone(&self.root), path.clone(), depth, metadata);
+
+            if !self.options.accepts(&entry) {
+                continue;
+            }
+
+            if !descend {
+                if in_range {
+                    return Some(Ok(entry));
+                }
+
+                continue;
+            }
+
+            let mut pending = Pending {
+                path,
+                depth,
+                device,
+                inode,
+                entry: None,
+            };
+
+            if self.options.contents_first {
+                pending.entry = in_range.then_some(entry);
+                self.pending = Some(pending);
+
+                continue;
+            }
+
+            self.pending = Some(pending);
+            if in_range {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+/// Results are tagged with the index of the root they came from, so that sorting keeps the roots
+/// in the order they were given.
+type Tagged = (usize, WalkResult);
+
+enum Source {
+    Streaming(Receiver<Tagged>),
+    Sorted(vec::IntoIter<Tagged>),
+}
+
+/// Worker threads read directories and stat their contents, handing entries over through a
+/// channel. A directory is always sent before anything inside it, which is what lets skipping a
+/// directory work by dropping whatever arrives from underneath it.
+struct Parallel {
+    source: Source,
+    last_dir: Option<PathBuf>,
+    skipped: HashSet<PathBuf>,
+}
+
+enum Task {
+    Root(usize, PathBuf),
+    Dir(DirJob),
+}
+
+struct DirJob {
+    root_index: usize,
+    path: PathBuf,
+    depth: usize,
+    root: Arc<Path>,
+    root_device: u64,
+    ancestors: Arc<Ancestor>,
+}
+
+/// Where the entries a worker is about to visit sit in the walk.
+struct Walked {
+    root_index: usize,
+    root: Arc<Path>,
+    root_device: Option<u64>,
+    ancestors: Option<Arc<Ancestor>>,
+}
+
+struct Ancestor {
+    device: u64,
+    inode: u64,
+    path: PathBuf,
+    parent: Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn find(mut ancestor: Option<&Ancestor>, device: u64, inode: u64) -> Option<&Ancestor> {
+        while let Some(current) = ancestor {
+            if current.device == device && current.inode == inode {
+                return Some(current);
+            }
+
+            ancestor = current.parent.as_deref();
+        }
+
+        None
+    }
+}
+
+struct Queue {
+    tasks: Vec<Task>,
+    active: usize,
+    done: bool,
+}
+
+struct Shared {
+    options: WalkOptions,
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+impl Parallel {
+    fn new(roots: Vec<PathBuf>, options: WalkOptions) -> Self {
+        let threads = options.threads;
+        let sort = options.sort;
+        let tasks = roots
+            .into_iter()
+            .enumerate()
+            .rev()
+            .map(|(root_index, root)| Task::Root(root_index, root))
+            .collect();
+        let shared = Arc::new(Shared {
+            options,
+            queue: Mutex::new(Queue {
+                tasks,
+                active: 0,
+                done: false,
+            }),
+            available: Condvar::new(),
+        });
+
+        let (sender, receiver) = mpsc::sync_channel::<Tagged>(CHANNEL_CAPACITY);
+        for _ in 0..threads {
+            let shared = Arc::clone(&shared);
+            let sender = sender.clone();
+
+            thread::spawn(move || shared.work(&sender));
+        }
+        drop(sender);
+
+        let source = if sort {
+            let mut results: Vec<Tagged> = receiver.into_iter().collect();
+            results.sort_by(|(a_index, a), (b_index, b)| {
+                a_index
+                    .cmp(b_index)
+                    .then_with(|| result_path(a).cmp(result_path(b)))
+            });
+
+            Source::Sorted(results.into_iter())
+        } else {
+            Source::Streaming(receiver)
+        };
+
+        Self {
+            source,
+            last_dir: None,
+            skipped: HashSet::new(),
+        }
+    }
+}
+
+fn result_path(result: &WalkResult) -> &Path {
+    match result {
+        Ok(entry) => entry.path(),
+        Err(err) => err.path(),
+    }
+}
+
+impl Iterator for Parallel {
+    type Item = WalkResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (_, result) = match &mut self.source {
+                Source::Streaming(receiver) => receiver.recv().ok()?,
+                Source::Sorted(results) => results.next()?,
+            };
+
+            if !self.skipped.is_empty() {
+                let path = result_path(&result);
+                let mut ancestors = path.ancestors();
+                if result.is_ok() {
+                    ancestors.next();
+                }
+
+                if ancestors.any(|ancestor| self.skipped.contains(ancestor)) {
+                    continue;
+                }
+            }
+
+            self.last_dir = match &result {
+                Ok(entry) if entry.metadata().is_dir() => Some(entry.path().to_path_buf()),
+                _ => None,
+            };
+
+            return Some(result);
+        }
+    }
+}
+
+impl Shared {
+    fn work(&self, sender: &SyncSender<Tagged>) {
+        while let Some(task) = self.pop() {
+            let keep_going = match task {
+                Task::Root(root_index, path) => {
+                    let root = Arc::from(path.as_path());
+                    let walked = Walked {
+                        root_index,
+                        root,
+                        root_device: None,
+                        ancestors: None,
+                    };
+
+                    self.visit(sender, &walked, path, 0)
+                }
+                Task::Dir(job) => self.read(sender, job),
+            };
+
+            self.finish(keep_going);
+        }
+    }
+
+    fn pop(&self) -> Option<Task> {
+        let mut queue = self.queue.lock().unwrap();
+
+        loop {
+            if queue.done {
+                return None;
+            }
+
+            if let Some(task) = queue.tasks.pop() {
+                queue.active += 1;
+                return Some(task);
+            }
+
+            if queue.active == 0 {
+                queue.done = true;
+                self.available.notify_all();
+                return None;
+            }
+
+            queue = self.available.wait(queue).unwrap();
+        }
+    }
+
+    fn push(&self, task: Task) {
+        self.queue.lock().unwrap().tasks.push(task);
+        self.available.notify_one();
+    }
+
+    /// A worker that can't send anymore means nobody is listening, so everyone stops.
+    fn finish(&self, keep_going: bool) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.active -= 1;
+
+        if !keep_going {
+            queue.done = true;
+        }
+
+        if queue.done || (queue.tasks.is_empty() && queue.active == 0) {
+            self.available.notify_all();
+        }
+    }
+
+    fn read(&self, sender: &SyncSender<Tagged>, job: DirJob) -> bool {
+        let dir = match fs::read_dir(&job.path) {
+            Ok(dir) => dir,
+            Err(err) => {
+                return sender
+                    .send((job.root_index, Err(WalkError::Io(job.path, err))))
+                    .is_ok()
+            }
+        };
+
+        let walked = Walked {
+            root_index: job.root_index,
+            root: job.root,
+            root_device: Some(job.root_device),
+            ancestors: Some(job.ancestors),
+        };
+
+        for dir_entry in dir {
+            let sent = match dir_entry {
+                Ok(dir_entry) => self.visit(sender, &walked, dir_entry.path(), job.depth + 1),
+                Err(err) => sender
+                    .send((walked.root_index, Err(WalkError::Io(job.path.clone(), err))))
+                    .is_ok(),
+            };
+
+            if !sent {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn visit(
+        &self,
+        sender: &SyncSender<Tagged>,
+        walked: &Walked,
+        path: PathBuf,
+        depth: usize,
+    ) -> bool {
+        let send = |result| sender.send((walked.root_index, result)).is_ok();
+
+        let metadata = match self.options.metadata(&path, depth) {
+            Ok(metadata) => metadata,
+            Err(err) => return send(Err(WalkError::Io(path, err))),
+        };
+
+        let (device, inode) = (metadata.dev(), metadata.ino());
+        let root_device = walked.root_device.unwrap_or(device);
+        let descend = self.options.descends(&metadata, depth, root_device);
+        let in_range = self.options.in_range(depth);
+
+        let entry = Entry::new(Arc::clone(&walked.root), path, depth, metadata);
+        if !self.options.accepts(&entry) {
+            return true;
+        }
+
+        let loop_error = descend
+            .then(|| Ancestor::find(walked.ancestors.as_deref(), device, inode))
+            .flatten()
+            .map(|ancestor| WalkError::Loop(entry.path().to_path_buf(), ancestor.path.clone()));
+
+        let job = (descend && loop_error.is_none()).then(|| DirJob {
+            root_index: walked.root_index,
+            path: entry.path().to_path_buf(),
+            depth,
+            root: Arc::clone(&walked.root),
+            root_device,
+            ancestors: Arc::new(Ancestor {
+                device,
+                inode,
+                path: entry.path().to_path_buf(),
+                parent: walked.ancestors.clone(),
+            }),
+        });
+
+        if in_range && !send(Ok(entry)) {
+            return false;
+        }
+
+        if let Some(loop_error) = loop_error {
+            return send(Err(loop_error));
+        }
+
+        if let Some(job) = job {
+            self.push(Task::Dir(job));
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+        path::Path,
+    };
+
+    use tempfile::TempDir;
+
+    use super::{Follow, WalkError, Walker};
+
+    fn tree() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["a/b", "a/c", "d"] {
+            fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        for path in ["a/b/1.txt", "a/2.txt", "d/3.txt", "4.txt"] {
+            fs::write(dir.path().join(path), path).unwrap();
+        }
+
+        dir
+    }
+
+    fn relative_paths(root: &Path, walker: Walker) -> Vec<String> {
+        walker
+            .build()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let relative = entry.path().strip_prefix(root).unwrap();
+
+                relative.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sorted_walk() {
+        let dir = tree();
+        let root = dir.path();
+
+        assert_eq!(
+            relative_paths(root, Walker::new(root).sort(true)),
+            vec!["", "4.txt", "a", "a/2.txt", "a/b", "a/b/1.txt", "a/c", "d", "d/3.txt"],
+        );
+        assert_eq!(
+            relative_paths(root, Walker::new(root).sort(true).threads(4)),
+            relative_paths(root, Walker::new(root).sort(true)),
+        );
+    }
+
+    #[test]
+    fn test_depth_limits_and_contents_first() {
+        let dir = tree();
+        let root = dir.path();
+
+        assert_eq!(
+            relative_paths(root, Walker::new(root).sort(true).min_depth(1).max_depth(1)),
+            vec!["4.txt", "a", "d"],
+        );
+        assert_eq!(
+            relative_paths(root, Walker::new(root.join("a")).sort(true).contents_first(true)),
+            vec!["a/2.txt", "a/b/1.txt", "a/b", "a/c", "a"],
+        );
+    }
+
+    #[test]
+    fn test_filters_and_roots() {
+        let dir = tree();
+        let root = dir.path();
+
+        assert_eq!(
+            relative_paths(
+                root,
+                Walker::new(root.join("a"))
+                    .root(root.join("d"))
+                    .sort(true)
+                    .filter(|entry| entry.file_name() != "b"),
+            ),
+            vec!["a", "a/2.txt", "a/c", "d", "d/3.txt"],
+        );
+    }
+
+    #[test]
+    fn test_skip_current_dir() {
+        let dir = tree();
+        let root = dir.path();
+
+        for threads in [1, 4] {
+            let mut walk = Walker::new(root).sort(true).threads(threads).build();
+            let mut paths = Vec::new();
+
+            while let Some(entry) = walk.next() {
+                let entry = entry.unwrap();
+                if entry.file_name() == "a" {
+                    walk.skip_current_dir();
+                }
+
+                paths.push(entry.path().strip_prefix(root).unwrap().to_path_buf());
+            }
+
+            assert_eq!(paths.len(), 5, "with {} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_symlink_loop() {
+        let dir = tree();
+        let root = dir.path();
+        symlink("..", root.join("a/up")).unwrap();
+
+        for threads in [1, 4] {
+            let mut paths = Vec::new();
+            let mut loops = Vec::new();
+            for entry in Walker::new(root)
+                .sort(true)
+                .follow(Follow::Always)
+                .threads(threads)
+                .build()
+            {
+                match entry {
+                    Ok(entry) => paths.push(entry.path().strip_prefix(root).unwrap().to_path_buf()),
+                    Err(WalkError::Loop(path, ancestor)) => loops.push((path, ancestor)),
+                    Err(err) => panic!("{}", err),
+                }
+            }
+
+            // the link back up is reported once and nothing below it is walked
+            assert_eq!(
+                loops,
+                [(root.join("a/up"), root.to_path_buf())],
+                "with {} threads",
+                threads
+            );
+            let below: Vec<_> = paths
+                .iter()
+                .filter(|path| path.starts_with("a/up"))
+                .collect();
+            assert_eq!(below, [Path::new("a/up")], "with {} threads", threads);
+            assert_eq!(paths.len(), 10, "with {} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_follow_roots() {
+        let dir = tree();
+        let root = dir.path();
+        symlink(root.join("a"), root.join("link")).unwrap();
+        symlink("../d", root.join("a/d")).unwrap();
+
+        // only the root link is followed, the one below it is reported as a link
+        let walk: Vec<_> = Walker::new(root.join("link"))
+            .sort(true)
+            .follow(Follow::Roots)
+            .build()
+            .map(|entry| entry.unwrap())
+            .collect();
+        let paths: Vec<_> = walk
+            .iter()
+            .map(|entry| entry.path().strip_prefix(root).unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "link",
+                "link/2.txt",
+                "link/b",
+                "link/b/1.txt",
+                "link/c",
+                "link/d"
+            ]
+            .map(Path::new),
+        );
+        assert!(walk[0].metadata().is_dir());
+        assert!(walk[5].metadata().file_type().is_symlink());
+
+        // without following, the root link isn't descended either
+        assert_eq!(
+            relative_paths(root, Walker::new(root.join("link")).follow(Follow::Never)),
+            vec!["link"],
+        );
+        assert_eq!(
+            relative_paths(
+                root,
+                Walker::new(root.join("link"))
+                    .sort(true)
+                    .follow(Follow::Always)
+            )
+            .len(),
+            7,
+        );
+    }
+
+    #[test]
+    fn test_same_file_system() {
+        let dir = tree();
+        let metadata = fs::metadata(dir.path().join("a")).unwrap();
+        let (_, options) = Walker::new(dir.path()).same_file_system(true).into_parts();
+
+        // a directory on another device than the root's is reported but not descended
+        assert!(options.descends(&metadata, 1, metadata.dev()));
+        assert!(!options.descends(&metadata, 1, metadata.dev() + 1));
+
+        let (_, options) = Walker::new(dir.path()).into_parts();
+        assert!(options.descends(&metadata, 1, metadata.dev() + 1));
+    }
+
+    #[test]
+    fn test_unreadable_dir() {
+        // root reads the directory regardless
+        if users::get_effective_uid() == 0 {
+            return;
+        }
+
+        let dir = tree();
+        let root = dir.path();
+        fs::set_permissions(root.join("a"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        for threads in [1, 4] {
+            let mut paths = Vec::new();
+            let mut errors = Vec::new();
+            for entry in Walker::new(root).sort(true).threads(threads).build() {
+                match entry {
+                    Ok(entry) => paths.push(entry.path().strip_prefix(root).unwrap().to_path_buf()),
+                    Err(err) => errors.push(err.to_string()),
+                }
+            }
+
+            // the directory is listed and reported, and the rest of the walk goes on
+            let message = format!("'{}': Permission denied", root.join("a").display());
+            assert_eq!(errors, [message], "with {} threads", threads);
+            assert_eq!(
+                paths,
+                ["", "4.txt", "a", "d", "d/3.txt"].map(Path::new),
+                "with {} threads",
+                threads
+            );
+        }
+
+        fs::set_permissions(root.join("a"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use serde_json::json;
+
+use crate::{
+    action::Context,
+    entry::Entry,
+    expr::Expr,
+    io_error_message,
+    walk::{Walk, WalkOptions},
+};
+
+/// Room for a few hundred events per read; inotify queues the rest.
+const EVENT_BUFFER_BYTES: usize = 64 * 1024;
+
+/// A file is reported as modified once whoever wrote to it has closed it, rather than on every
+/// single write.
+const WATCH_MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::CLOSE_WRITE)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::ONLYDIR);
+
+struct WatchedDir {
+    path: PathBuf,
+    depth: usize,
+    root: Arc<Path>,
+    root_device: u64,
+}
+
+/// An event as read, owned so that handling it can add and remove watches.
+struct RawEvent {
+    wd: WatchDescriptor,
+    mask: EventMask,
+    cookie: u32,
+    name: Option<OsString>,
+}
+
+/// Keeps track of every directory a walk went into and turns inotify's events about them into
+/// changes to the entries the expression matches.
+///
+/// A deleted entry can't be tested anymore, so the paths that matched are remembered instead: an
+/// entry that goes away is reported if it matched when it was last seen.
+pub struct Watcher<'a> {
+    inotify: Inotify,
+    expr: &'a Expr,
+    options: WalkOptions,
+    json: bool,
+    dirs: HashMap<WatchDescriptor, WatchedDir>,
+    matched: HashSet<PathBuf>,
+    root_devices: HashMap<PathBuf, u64>,
+    failed: bool,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(expr: &'a Expr, options: WalkOptions, json: bool) -> io::Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init()?,
+            expr,
+            options,
+            json,
+            dirs: HashMap::new(),
+            matched: HashSet::new(),
+            root_devices: HashMap::new(),
+            failed: false,
+        })
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Takes note of an entry of the initial walk. A directory is watched as soon as the walk
+    /// gets to it, before its contents are read, so nothing created meanwhile goes unnoticed.
+    pub fn visit(&mut self, entry: &Entry, matched: bool, pruned: bool) {
+        if entry.depth() == 0 {
+            self.root_devices
+                .insert(entry.root().to_path_buf(), entry.metadata().dev());
+        }
+
+        let root_device = self.root_devices.get(entry.root()).copied().unwrap_or(0);
+        let root = Arc::from(entry.root());
+        self.note(entry, root, root_device, matched, pruned);
+    }
+
+    /// Waits for changes and reports them until reading events or writing `out` fails.
+    pub fn watch(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let mut buffer = vec![0; EVENT_BUFFER_BYTES];
+
+        loop {
+            let events: Vec<RawEvent> = self
+                .inotify
+                .read_events_blocking(&mut buffer)?
+                .map(|event| RawEvent {
+                    wd: event.wd,
+                    mask: event.mask,
+                    cookie: event.cookie,
+                    name: event.name.map(OsString::from),
+                })
+                .collect();
+
+            let mut moved_from: Option<(u32, PathBuf)> = None;
+
+            for event in events {
+                if event.mask.contains(EventMask::MOVED_TO) {
+                    if let Some((_, from)) =
+                        moved_from.take_if(|(cookie, _)| *cookie == event.cookie)
+                    {
+                        self.renamed(&event, from, out)?;
+                        continue;
+                    }
+                }
+
+                if let Some((_, from)) = moved_from.take() {
+                    self.moved_away(&from, out)?;
+                }
+
+                if event.mask.contains(EventMask::MOVED_FROM) {
+                    moved_from = self.path(&event).map(|path| (event.cookie, path));
+                } else {
+                    self.handle(&event, out)?;
+                }
+            }
+
+            // The other half of a rename always comes in the same read, so this was moved out
+            // from under the watched directories.
+            if let Some((_, from)) = moved_from {
+                self.moved_away(&from, out)?;
+            }
+
+            out.flush()?;
+        }
+    }
+
+    fn handle(&mut self, event: &RawEvent, out: &mut dyn Write) -> io::Result<()> {
+        if event.mask.contains(EventMask::Q_OVERFLOW) {
+            eprintln!("find: too many changes at once; some of them were missed");
+            self.failed = true;
+            return Ok(());
+        }
+
+        if event.mask.contains(EventMask::IGNORED) {
+            self.dirs.remove(&event.wd);
+            return Ok(());
+        }
+
+        let Some(path) = self.path(event) else {
+            return Ok(());
+        };
+
+        if event.mask.contains(EventMask::CREATE) || event.mask.contains(EventMask::MOVED_TO) {
+            self.created(&event.wd, path, out)
+        } else if event.mask.contains(EventMask::CLOSE_WRITE) {
+            self.modified(&event.wd, path, out)
+        } else if event.mask.contains(EventMask::DELETE) && self.matched.remove(&path) {
+            self.report(out, "deleted", &path, None)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Something new in a watched directory, and whatever is already inside it if it's a
+    /// directory that was moved in or filled before its watch was in place.
+    fn created(
+        &mut self,
+        wd: &WatchDescriptor,
+        path: PathBuf,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let Some(entry) = self.entry(wd, path) else {
+            return Ok(());
+        };
+        let (root, root_device) = {
+            let dir = &self.dirs[wd];
+            (Arc::clone(&dir.root), dir.root_device)
+        };
+
+        let (matched, pruned) = self.evaluate(&entry);
+        if matched {
+            self.report(out, "created", entry.path(), None)?;
+        }
+        if !self.note(&entry, Arc::clone(&root), root_device, matched, pruned) {
+            return Ok(());
+        }
+
+        let options = WalkOptions {
+            max_depth: self.options.max_depth.map(|max| max - entry.depth()),
+            min_depth: 1,
+            contents_first: false,
+            ..self.options.clone()
+        };
+        let mut walk = Walk::new(vec![entry.path().to_path_buf()], options);
+
+        while let Some(result) = walk.next() {
+            let Ok(inner) = result else {
+                continue;
+            };
+            let inner = Entry::new(
+                Arc::clone(&root),
+                inner.path().to_path_buf(),
+                entry.depth() + inner.depth(),
+                inner.metadata().clone(),
+            );
+
+            let (matched, pruned) = self.evaluate(&inner);
+            if matched {
+                self.report(out, "created", inner.path(), None)?;
+            }
+            if pruned {
+                walk.skip_current_dir();
+            }
+            self.note(&inner, Arc::clone(&root), root_device, matched, pruned);
+        }
+
+        Ok(())
+    }
+
+    fn modified(
+        &mut self,
+        wd: &WatchDescriptor,
+        path: PathBuf,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let Some(entry) = self.entry(wd, path) else {
+            return Ok(());
+        };
+
+        if self.evaluate(&entry).0 {
+            self.matched.insert(entry.path().to_path_buf());
+            self.report(out, "modified", entry.path(), None)
+        } else if self.matched.remove(entry.path()) {
+            // It no longer matches, which as far as the expression goes is as good as gone.
+            self.report(out, "deleted", entry.path(), None)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moved within the watched directories. It's reported if it matched before or matches now;
+    /// anything inside a renamed directory is carried over without being reported one by one.
+    fn renamed(&mut self, event: &RawEvent, from: PathBuf, out: &mut dyn Write) -> io::Result<()> {
+        let Some(to) = self.path(event) else {
+            return Ok(());
+        };
+
+        let was_matched = self.matched.remove(&from);
+        self.matched = self
+            .matched
+            .drain()
+            .map(|path| match path.strip_prefix(&from) {
+                Ok(rest) => to.join(rest),
+                Err(_) => path,
+            })
+            .collect();
+        for dir in self.dirs.values_mut() {
+            if let Ok(rest) = dir.path.strip_prefix(&from) {
+                dir.path = to.join(rest);
+            }
+        }
+
+        let Some(entry) = self.entry(&event.wd, to) else {
+            return match was_matched {
+                true => self.report(out, "deleted", &from, None),
+                false => Ok(()),
+            };
+        };
+
+        let matched = self.evaluate(&entry).0;
+        if matched {
+            self.matched.insert(entry.path().to_path_buf());
+        }
+        if was_matched || matched {
+            self.report(out, "renamed", entry.path(), Some(&from))?;
+        }
+
+        Ok(())
+    }
+
+    /// Moved somewhere nothing is watched, so it's gone along with everything inside it.
+    fn moved_away(&mut self, from: &Path, out: &mut dyn Write) -> io::Result<()> {
+        let gone: Vec<WatchDescriptor> = self
+            .dirs
+            .iter()
+            .filter(|(_, dir)| dir.path.starts_with(from))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in gone {
+            self.dirs.remove(&wd);
+            let _ = self.inotify.watches().remove(wd);
+        }
+
+        let mut deleted: Vec<PathBuf> = self
+            .matched
+            .iter()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+        deleted.sort();
+
+        for path in deleted {
+            self.matched.remove(&path);
+            self.report(out, "deleted", &path, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remembers a match and watches a directory the walk would go into. Returns whether it did
+    /// watch it.
+    fn note(
+        &mut self,
+        entry: &Entry,
+        root: Arc<Path>,
+        root_device: u64,
+        matched: bool,
+        pruned: bool,
+    ) -> bool {
+        if matched {
+            self.matched.insert(entry.path().to_path_buf());
+        }
+
+        if pruned
+            || !self
+                .options
+                .descends(entry.metadata(), entry.depth(), root_device)
+        {
+            return false;
+        }
+
+        match self.inotify.watches().add(entry.path(), WATCH_MASK) {
+            Ok(wd) => {
+                self.dirs.insert(
+                    wd,
+                    WatchedDir {
+                        path: entry.path().to_path_buf(),
+                        depth: entry.depth(),
+                        root,
+                        root_device,
+                    },
+                );
+                true
+            }
+            Err(err) => {
+                eprintln!(
+                    "find: '{}': {}",
+                    entry.path().display(),
+                    io_error_message(&err)
+                );
+                self.failed = true;
+                false
+            }
+        }
+    }
+
+    fn path(&self, event: &RawEvent) -> Option<PathBuf> {
+        let dir = self.dirs.get(&event.wd)?;
+
+        Some(dir.path.join(event.name.as_ref()?))
+    }
+
+    /// Looks the path up the way the walk would have and lets the walk's filters have their say.
+    /// Entries that have already disappeared again are passed over.
+    fn entry(&self, wd: &WatchDescriptor, path: PathBuf) -> Option<Entry> {
+        let dir = self.dirs.get(wd)?;
+        let depth = dir.depth + 1;
+        let metadata = self.options.metadata(&path, depth).ok()?;
+        let entry = Entry::new(Arc::clone(&dir.root), path, depth, metadata);
+
+        self.options.accepts(&entry).then_some(entry)
+    }
+
+    /// Returns whether the expression matches the entry and whether it pruned it.
+    fn evaluate(&self, entry: &Entry) -> (bool, bool) {
+        let mut sink = io::sink();
+        let mut ctx = Context::new(SystemTime::now(), &mut sink);
+        let matched = self.options.in_range(entry.depth()) && self.expr.evaluate(entry, &mut ctx);
+
+        (matched, ctx.take_pruned())
+    }
+
+    fn report(
+        &self,
+        out: &mut dyn Write,
+        event: &str,
+        path: &Path,
+        from: Option<&Path>,
+    ) -> io::Result<()> {
+        if self.json {
+            let mut line = json!({ "event": event, "path": path.to_string_lossy() });
+            if let Some(from) = from {
+                line["from"] = json!(from.to_string_lossy());
+            }
+
+            return writeln!(out, "{}", line);
+        }
+
+        out.write_all(event.as_bytes())?;
+        out.write_all(b" ")?;
+        if let Some(from) = from {
+            out.write_all(from.as_os_str().as_bytes())?;
+            out.write_all(b" -> ")?;
+        }
+        out.write_all(path.as_os_str().as_bytes())?;
+        out.write_all(b"\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, ErrorKind, Write},
+        path::Path,
+        sync::mpsc::{self, Sender},
+        thread,
+        time::Duration,
+    };
+
+    use clap::Parser;
+    use serde_json::{json, Value};
+
+    use crate::FindCommand;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Keeps what is written, tells the test how many lines it has each time it's flushed, and
+    /// goes away like the reader of a pipe once it has the lines it wants.
+    struct Reader {
+        out: Vec<u8>,
+        lines: usize,
+        flushed: Sender<usize>,
+    }
+
+    impl Write for Reader {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let lines = self.out.iter().filter(|&&b| b == b'\n').count();
+            let _ = self.flushed.send(lines);
+            match lines >= self.lines {
+                true => Err(ErrorKind::BrokenPipe.into()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    /// Runs `find --watch EXTRA DIR -name '*.log'` while `change` changes what is in DIR, and
+    /// returns the first `lines` lines it printed. `change` is handed a way to wait for a number
+    /// of lines, since a file that is already gone by the time its event is read isn't reported.
+    fn watch(
+        extra: &[&str],
+        lines: usize,
+        change: impl FnOnce(&Path, &mut dyn FnMut(usize)),
+    ) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("old.log"), "").unwrap();
+
+        let mut args = vec![String::from("find"), String::from("--watch")];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        args.push(dir.path().display().to_string());
+        args.extend(["-name", "*.log"].map(String::from));
+        let cmd = FindCommand::try_parse_from(args).unwrap();
+
+        let (flushed, ready) = mpsc::channel();
+        let (done, output) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = Reader {
+                out: Vec::new(),
+                lines,
+                flushed,
+            };
+            let result = cmd.exec(&mut reader).map(|result| result.exit_code());
+            let _ = done.send((result, reader.out));
+        });
+
+        // the initial listing is flushed once every directory is watched
+        let mut printed = ready.recv_timeout(TIMEOUT).expect("initial walk");
+        change(dir.path(), &mut |lines| {
+            while printed < lines {
+                printed = ready.recv_timeout(TIMEOUT).expect("watch events");
+            }
+        });
+
+        let (result, out) = output.recv_timeout(TIMEOUT).expect("watch events");
+        assert_eq!(result.unwrap(), 0);
+
+        let root = dir.path().display().to_string();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.replace(&root, "DIR"))
+            .collect()
+    }
+
+    fn change(dir: &Path, wait: &mut dyn FnMut(usize)) {
+        fs::write(dir.join("skipped.txt"), "").unwrap();
+        fs::write(dir.join("a.log"), "").unwrap();
+        wait(3);
+        fs::rename(dir.join("a.log"), dir.join("b.log")).unwrap();
+        wait(4);
+        fs::remove_file(dir.join("b.log")).unwrap();
+    }
+
+    #[test]
+    fn test_watch() {
+        assert_eq!(
+            watch(&[], 5, change),
+            [
+                "DIR/old.log",
+                "created DIR/a.log",
+                "modified DIR/a.log",
+                "renamed DIR/a.log -> DIR/b.log",
+                "deleted DIR/b.log",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_watch_json() {
+        let lines = watch(&["--json"], 5, change);
+        let events: Vec<Value> = lines[1..]
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines[0], "DIR/old.log");
+        assert_eq!(
+            events,
+            [
+                json!({ "event": "created", "path": "DIR/a.log" }),
+                json!({ "event": "modified", "path": "DIR/a.log" }),
+                json!({ "event": "renamed", "path": "DIR/b.log", "from": "DIR/a.log" }),
+                json!({ "event": "deleted", "path": "DIR/b.log" }),
+            ],
+        );
+    }
+}
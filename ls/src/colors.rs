@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, Metadata},
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+use colored::{Color, ColoredString, Colorize};
+
+/// What GNU ls uses when `LS_COLORS` isn't set.
+const DEFAULT_COLORS: &str =
+    "di=01;34:ln=01;36:or=40;31;01:pi=40;33:so=01;35:do=01;35:bd=40;33;01:\
+cd=40;33;01:su=37;41:sg=30;43:st=37;44:ow=34;42:tw=30;42:ex=01;32";
+
+/// A `dircolors` database, as found in `LS_COLORS`: two-letter file type keys (`di`, `ln`,
+/// `ex`, ...) and `*suffix` patterns, each mapped to SGR parameters like `01;34`.
+#[derive(Debug, Default)]
+pub struct LsColors {
+    types: HashMap<String, String>,
+    suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+    pub fn from_env() -> Self {
+        match env::var("LS_COLORS") {
+            Ok(spec) if !spec.is_empty() => Self::parse(&spec),
+            _ => Self::parse(DEFAULT_COLORS),
+        }
+    }
+
+    /// Entries that don't look like `key=value` are skipped, like dircolors leaves them.
+    pub fn parse(spec: &str) -> Self {
+        let mut colors = Self::default();
+
+        for (key, value) in spec.split(':').filter_map(|entry| entry.split_once('=')) {
+            match key.strip_prefix('*') {
+                Some(suffix) => colors
+                    .suffixes
+                    .push((suffix.to_lowercase(), value.to_string())),
+                None => {
+                    colors.types.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+
+        colors
+    }
+
+    /// Colors a name the way GNU ls would for the file at `path`, whose metadata is `metadata`
+    /// (not following symlinks).
+    pub fn paint(&self, name: String, path: &Path, metadata: &Metadata) -> ColoredString {
+        match self.sgr(&name, path, metadata) {
+            Some(sgr) => style(name, sgr),
+            None => name.normal(),
+        }
+    }
+
+    /// Colors the target of a symbolic link that points nowhere: `mi`, or `or` like the link.
+    pub fn paint_missing(&self, name: String) -> ColoredString {
+        match self.get("mi").or_else(|| self.get("or")) {
+            Some(sgr) => style(name, sgr),
+            None => name.normal(),
+        }
+    }
+
+    fn sgr(&self, name: &str, path: &Path, metadata: &Metadata) -> Option<&str> {
+        let file_type = metadata.file_type();
+        let mode = metadata.permissions().mode();
+
+        let key = if file_type.is_symlink() {
+            match fs::metadata(path) {
+                Err(_) if self.types.contains_key("or") => "or",
+                Ok(target) if self.get("ln") == Some("target") => {
+                    return self.sgr(name, path, &target)
+                }
+                _ => "ln",
+            }
+        } else if file_type.is_dir() {
+            match (mode & 0o1000 != 0, mode & 0o002 != 0) {
+                (true, true) => "tw",
+                (false, true) => "ow",
+                (true, false) => "st",
+                (false, false) => "di",
+            }
+        } else if file_type.is_fifo() {
+            "pi"
+        } else if file_type.is_socket() {
+            "so"
+        } else if file_type.is_block_device() {
+            "bd"
+        } else if file_type.is_char_device() {
+            "cd"
+        } else if mode & 0o4000 != 0 {
+            "su"
+        } else if mode & 0o2000 != 0 {
+            "sg"
+        } else if mode & 0o111 != 0 {
+            "ex"
+        } else if metadata.nlink() > 1 && self.types.contains_key("mh") {
+            "mh"
+        } else {
+            // only plain files are colored by their names
+            let name = name.to_lowercase();
+            if let Some((_, sgr)) = self
+                .suffixes
+                .iter()
+                .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            {
+                return Some(sgr);
+            }
+
+            "fi"
+        };
+
+        // GNU ls falls back on the color of plain files for types without one of their own
+        self.get(key)
+            .or_else(|| self.get("fi"))
+            .or_else(|| self.get("no"))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.types
+            .get(key)
+            .map(String::as_str)
+            .filter(|sgr| !sgr.is_empty() && *sgr != "0" && *sgr != "00")
+    }
+}
+
+/// Applies SGR parameters (`01;38;5;208`, ...) to a name. Codes with no counterpart are left out.
+fn style(name: String, sgr: &str) -> ColoredString {
+    let codes: Vec<u8> = sgr
+        .split(';')
+        .filter_map(|code| code.parse().ok())
+        .collect();
+    let mut styled = name.normal();
+    let mut idx = 0;
+
+    while idx < codes.len() {
+        styled = match codes[idx] {
+            1 => styled.bold(),
+            2 => styled.dimmed(),
+            3 => styled.italic(),
+            4 => styled.underline(),
+            5 => styled.blink(),
+            7 => styled.reversed(),
+            8 => styled.hidden(),
+            9 => styled.strikethrough(),
+            code @ 30..=37 => styled.color(basic_color(code - 30)),
+            code @ 40..=47 => styled.on_color(basic_color(code - 40)),
+            code @ 90..=97 => styled.color(basic_color(code - 90 + 8)),
+            code @ 100..=107 => styled.on_color(basic_color(code - 100 + 8)),
+            code @ (38 | 48) => {
+                let color = match codes.get(idx + 1) {
+                    Some(5) => codes.get(idx + 2).map(|n| {
+                        idx += 2;
+                        palette_color(*n)
+                    }),
+                    Some(2) => codes.get(idx + 2..idx + 5).map(|rgb| {
+                        idx += 4;
+                        Color::TrueColor {
+                            r: rgb[0],
+                            g: rgb[1],
+                            b: rgb[2],
+                        }
+                    }),
+                    _ => None,
+                };
+
+                match (color, code) {
+                    (Some(color), 38) => styled.color(color),
+                    (Some(color), _) => styled.on_color(color),
+                    (None, _) => styled,
+                }
+            }
+            _ => styled,
+        };
+        idx += 1;
+    }
+
+    styled
+}
+
+fn basic_color(n: u8) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::White,
+        8 => Color::BrightBlack,
+        9 => Color::BrightRed,
+        10 => Color::BrightGreen,
+        11 => Color::BrightYellow,
+        12 => Color::BrightBlue,
+        13 => Color::BrightMagenta,
+        14 => Color::BrightCyan,
+        _ => Color::BrightWhite,
+    }
+}
+
+/// The xterm 256 color palette: the 16 basic colors, a 6x6x6 cube and 24 grays.
+fn palette_color(n: u8) -> Color {
+    match n {
+        0..=15 => basic_color(n),
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let n = n - 16;
+
+            Color::TrueColor {
+                r: level(n / 36),
+                g: level(n / 6 % 6),
+                b: level(n % 6),
+            }
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+
+            Color::TrueColor {
+                r: gray,
+                g: gray,
+                b: gray,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::LsColors;
+
+    #[test]
+    fn test_paint() {
+        colored::control::set_override(true);
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("ARCHIVE.TAR"), "").unwrap();
+        fs::write(root.join("plain"), "").unwrap();
+        std::os::unix::fs::symlink("missing", root.join("broken")).unwrap();
+
+        let colors = LsColors::parse("di=01;34:or=31:*.tar=01;31:fi=:bogus");
+        let paint = |name: &str| {
+            let path = root.join(name);
+            let metadata = fs::symlink_metadata(&path).unwrap();
+            colors.paint(name.to_string(), &path, &metadata).to_string()
+        };
+
+        assert_eq!(paint("dir"), "\x1b[1;34mdir\x1b[0m");
+        assert_eq!(paint("ARCHIVE.TAR"), "\x1b[1;31mARCHIVE.TAR\x1b[0m");
+        assert_eq!(paint("broken"), "\x1b[31mbroken\x1b[0m");
+        assert_eq!(paint("plain"), "plain");
+        assert_eq!(
+            colors.paint_missing("missing".to_string()).to_string(),
+            "\x1b[31mmissing\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::HashSet,
+    fs::{self, Metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// How much disk each of `dirs` takes up with everything below it, like `du -s` counts it. The
+/// directories are walked on up to as many threads as there are CPUs, and a file with several
+/// hard links counts once, under whichever directory gets to it first. Symbolic links below the
+/// directories aren't followed, and what can't be read counts as nothing.
+pub fn disk_usage(dirs: &[PathBuf]) -> Vec<u64> {
+    let seen = Mutex::new(HashSet::new());
+    let next = AtomicUsize::new(0);
+    let threads = thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(dirs.len());
+
+    let mut usages = vec![0; dirs.len()];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(dir) = dirs.get(idx) else {
+                            return done;
+                        };
+
+                        let metadata = fs::metadata(dir);
+                        done.push((idx, metadata.map_or(0, |m| usage(dir, &m, &seen))));
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (idx, usage) in worker.join().unwrap() {
+                usages[idx] = usage;
+            }
+        }
+    });
+
+    usages
+}
+
+fn usage(path: &Path, metadata: &Metadata, seen: &Mutex<HashSet<(u64, u64)>>) -> u64 {
+    if metadata.nlink() > 1
+        && !metadata.is_dir()
+        && !seen
+            .lock()
+            .unwrap()
+            .insert((metadata.dev(), metadata.ino()))
+    {
+        return 0;
+    }
+
+    let mut total = metadata.blocks() * 512;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            if let Ok(metadata) = entry.metadata() {
+                total += usage(&entry.path(), &metadata, seen);
+            }
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::MetadataExt};
+
+    use super::disk_usage;
+
+    #[test]
+    fn test_disk_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for name in ["a", "b", "a/nested", "empty"] {
+            fs::create_dir(root.join(name)).unwrap();
+        }
+        fs::write(root.join("a/nested/data"), vec![1; 20_000]).unwrap();
+        fs::hard_link(root.join("a/nested/data"), root.join("b/link")).unwrap();
+        fs::write(root.join("b/own"), vec![1; 5000]).unwrap();
+
+        let blocks = |path: &str| fs::symlink_metadata(root.join(path)).unwrap().blocks() * 512;
+        let dirs = ["a", "b", "empty"].map(|name| root.join(name));
+        let usages = disk_usage(&dirs);
+
+        // the data linked from both counts for whichever got to it first
+        let shared = blocks("a/nested/data");
+        let a = blocks("a") + blocks("a/nested");
+        let b = blocks("b") + blocks("b/own");
+        assert!(
+            usages[..2] == [a + shared, b] || usages[..2] == [a, b + shared],
+            "{:?}",
+            usages
+        );
+        assert_eq!(usages[2], blocks("empty"));
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    env,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+use git2::{Repository, Status, StatusOptions};
+
+/// The status characters from most to least worth showing when a directory sums up what's in it.
+const PRECEDENCE: [char; 7] = ['U', 'M', 'D', 'R', 'T', 'N', 'I'];
+
+/// Where files stand in the git repository a listed directory is in, read from its index and
+/// working tree with libgit2 rather than by running `git`.
+pub struct GitStatuses {
+    workdir: PathBuf,
+    cwd: PathBuf,
+    statuses: Vec<(PathBuf, Status)>,
+}
+
+impl GitStatuses {
+    /// Looks at `dir` and everything below it. Nothing when it isn't in a working tree or the
+    /// repository can't be read.
+    pub fn new(dir: &Path) -> Option<Self> {
+        let cwd = env::current_dir().ok()?;
+        let dir = normalize(&cwd.join(dir));
+        let repo = Repository::discover(&dir).ok()?;
+        let workdir = repo.workdir()?.canonicalize().ok()?;
+
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .include_ignored(true)
+            .renames_head_to_index(true);
+
+        let pathspec = dir.strip_prefix(&workdir).ok()?;
+        if !pathspec.as_os_str().is_empty() {
+            options.pathspec(pathspec).disable_pathspec_match(true);
+        }
+
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .ok()?
+            .iter()
+            .map(|entry| {
+                let path = PathBuf::from(OsStr::from_bytes(entry.path_bytes()));
+                (path, entry.status())
+            })
+            .collect();
+
+        Some(Self {
+            workdir,
+            cwd,
+            statuses,
+        })
+    }
+
+    /// Two characters: the change staged in the index, then the one in the working tree. Each is
+    /// `N` new, `M` modified, `D` deleted, `R` renamed, `T` type changed, `I` ignored, `U`
+    /// conflicted or `-` unchanged. A directory shows what matters most among its contents, but
+    /// is only ignored when it is itself; a path outside the working tree has no status.
+    pub fn status(&self, path: &Path) -> Option<String> {
+        let path = normalize(&self.cwd.join(path));
+        let path = path.strip_prefix(&self.workdir).ok()?;
+
+        let mut staged = '-';
+        let mut unstaged = '-';
+        for (other, status) in &self.statuses {
+            // untracked and ignored directories are reported as a whole, so what's above a path
+            // counts as much as what's below it
+            let below = other.starts_with(path) && other != path;
+            if !below && !path.starts_with(other) {
+                continue;
+            }
+
+            // something ignored inside a directory doesn't make the directory ignored
+            let (other_staged, other_unstaged) = chars(*status);
+            if below && other_unstaged == 'I' {
+                continue;
+            }
+
+            staged = most_worth_showing(staged, other_staged);
+            unstaged = most_worth_showing(unstaged, other_unstaged);
+        }
+
+        Some(format!("{}{}", staged, unstaged))
+    }
+}
+
+fn chars(status: Status) -> (char, char) {
+    if status.is_conflicted() {
+        return ('U', 'U');
+    }
+    if status.is_ignored() {
+        return ('-', 'I');
+    }
+
+    let staged = if status.is_index_new() {
+        'N'
+    } else if status.is_index_modified() {
+        'M'
+    } else if status.is_index_deleted() {
+        'D'
+    } else if status.is_index_renamed() {
+        'R'
+    } else if status.is_index_typechange() {
+        'T'
+    } else {
+        '-'
+    };
+
+    let unstaged = if status.is_wt_new() {
+        'N'
+    } else if status.is_wt_modified() {
+        'M'
+    } else if status.is_wt_deleted() {
+        'D'
+    } else if status.is_wt_renamed() {
+        'R'
+    } else if status.is_wt_typechange() {
+        'T'
+    } else {
+        '-'
+    };
+
+    (staged, unstaged)
+}
+
+fn most_worth_showing(a: char, b: char) -> char {
+    let rank = |c: char| {
+        PRECEDENCE
+            .iter()
+            .position(|p| *p == c)
+            .unwrap_or(PRECEDENCE.len())
+    };
+
+    if rank(b) < rank(a) {
+        b
+    } else {
+        a
+    }
+}
+
+/// Takes `.` and `..` out of an absolute path without looking at the file system, so that `..`
+/// listed with -a ends up where it points.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use git2::{IndexAddOption, Repository, Signature};
+
+    use super::GitStatuses;
+
+    #[test]
+    fn test_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let repo = Repository::init(&root).unwrap();
+
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("committed"), "a").unwrap();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::write(root.join("src/.gitignore"), "build/\n").unwrap();
+        fs::write(root.join("src/clean"), "a").unwrap();
+        fs::write(root.join("src/nested/edited"), "a").unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        fs::write(root.join("src/nested/edited"), "b").unwrap();
+        fs::write(root.join("staged"), "a").unwrap();
+        index.add_path("staged".as_ref()).unwrap();
+        index.write().unwrap();
+        fs::write(root.join("untracked"), "a").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join("target/build"), "a").unwrap();
+        fs::create_dir(root.join("src/build")).unwrap();
+        fs::write(root.join("src/build/o"), "a").unwrap();
+        fs::create_dir(root.join("new")).unwrap();
+        fs::write(root.join("new/file"), "a").unwrap();
+
+        let statuses = GitStatuses::new(&root).unwrap();
+        let status = |path: &str| statuses.status(&root.join(path)).unwrap();
+
+        assert_eq!(status("committed"), "--");
+        assert_eq!(status("staged"), "N-");
+        assert_eq!(status("untracked"), "-N");
+        assert_eq!(status("src/nested/edited"), "-M");
+        assert_eq!(status("src/clean"), "--");
+        assert_eq!(status("src"), "-M");
+        assert_eq!(status("src/nested/.."), "-M");
+        assert_eq!(status("target"), "-I");
+        assert_eq!(status("target/build"), "-I");
+        assert_eq!(status("src/build/o"), "-I");
+        assert_eq!(status("new"), "-N");
+        assert_eq!(status("new/file"), "-N");
+        assert_eq!(statuses.status("/".as_ref()), None);
+
+        let statuses = GitStatuses::new(&root.join("src")).unwrap();
+        assert_eq!(statuses.status(&root.join("src/clean")).unwrap(), "--");
+        assert_eq!(statuses.status(&root.join("src/nested")).unwrap(), "-M");
+
+        // a clean directory with something ignored in it isn't ignored
+        fs::write(root.join("src/nested/edited"), "a").unwrap();
+        let statuses = GitStatuses::new(&root).unwrap();
+        assert_eq!(statuses.status(&root.join("src")).unwrap(), "--");
+        assert_eq!(statuses.status(&root.join("src/build")).unwrap(), "-I");
+    }
+}
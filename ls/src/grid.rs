@@ -0,0 +1,164 @@
+/// Space between two columns of the grid.
+const COLUMN_GAP: usize = 2;
+
+/// Lays cells out in as many columns as fit in `line_width`, the way GNU ls does: down the
+/// columns first, or along the rows when `across` is set. Each cell is its text along with the
+/// width it takes on screen, which may differ from its length for colored or wide text.
+/// Without a line width, everything goes on one line.
+pub fn grid(cells: &[(String, usize)], line_width: Option<usize>, across: bool) -> Vec<String> {
+    if cells.is_empty() {
+        return Vec::new();
+    }
+
+    let (rows, widths) = match line_width {
+        Some(line_width) => layout(cells, line_width, across),
+        None => (1, cells.iter().map(|(_, width)| *width).collect()),
+    };
+    let columns = widths.len();
+
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+
+            for (column, width) in widths.iter().enumerate() {
+                let Some((text, text_width)) = cells.get(index(row, column, rows, columns, across))
+                else {
+                    break;
+                };
+
+                line.push_str(text);
+                if column + 1 < columns
+                    && cells
+                        .get(index(row, column + 1, rows, columns, across))
+                        .is_some()
+                {
+                    line.push_str(&" ".repeat(width - text_width + COLUMN_GAP));
+                }
+            }
+
+            line
+        })
+        .collect()
+}
+
+fn index(row: usize, column: usize, rows: usize, columns: usize, across: bool) -> usize {
+    if across {
+        row * columns + column
+    } else {
+        column * rows + row
+    }
+}
+
+/// Picks the most columns that fit, and returns how many rows that takes and how wide each
+/// column is. Like GNU ls, every number of columns that could fit even with the narrowest
+/// cells is tried at once, widening each one's columns as the cells go by.
+fn layout(cells: &[(String, usize)], line_width: usize, across: bool) -> (usize, Vec<usize>) {
+    let narrowest = cells.iter().map(|(_, width)| *width).min().unwrap_or(0);
+    let max_columns = ((line_width + COLUMN_GAP) / (narrowest + COLUMN_GAP)).clamp(1, cells.len());
+
+    let mut layouts: Vec<Layout> = (1..=max_columns)
+        .map(|columns| Layout::new(cells.len(), columns, across, line_width))
+        .collect();
+    for (idx, (_, width)) in cells.iter().enumerate() {
+        for layout in layouts.iter_mut().filter(|layout| layout.fits) {
+            layout.add(idx, *width, across, line_width);
+        }
+    }
+
+    match layouts.into_iter().rev().find(|layout| layout.fits) {
+        Some(layout) => (layout.rows, layout.widths),
+        None => (cells.len(), vec![0]),
+    }
+}
+
+/// One number of columns being tried, with the widths its columns have so far.
+struct Layout {
+    rows: usize,
+    widths: Vec<usize>,
+    length: usize,
+    fits: bool,
+}
+
+impl Layout {
+    /// Down the columns, the rows needed for `columns` may hold every cell in fewer of them, and
+    /// only the columns that get cells count.
+    fn new(cells: usize, columns: usize, across: bool, line_width: usize) -> Self {
+        let rows = cells.div_ceil(columns);
+        let columns = if across {
+            columns
+        } else {
+            cells.div_ceil(rows)
+        };
+        let length = COLUMN_GAP * (columns - 1);
+
+        Self {
+            rows,
+            widths: vec![0; columns],
+            length,
+            fits: length <= line_width,
+        }
+    }
+
+    fn add(&mut self, idx: usize, width: usize, across: bool, line_width: usize) {
+        let column = if across {
+            idx % self.widths.len()
+        } else {
+            idx / self.rows
+        };
+
+        if width > self.widths[column] {
+            self.length += width - self.widths[column];
+            self.widths[column] = width;
+            self.fits = self.length <= line_width;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::grid;
+
+    fn cells(names: &[&str]) -> Vec<(String, usize)> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), name.chars().count()))
+            .collect()
+    }
+
+    #[test]
+    fn test_grid() {
+        let names = cells(&["a", "bb", "ccc", "dddd", "e"]);
+
+        assert_eq!(grid(&names, Some(80), false), vec!["a  bb  ccc  dddd  e"]);
+        assert_eq!(
+            grid(&names, Some(12), false),
+            vec!["a   ccc   e", "bb  dddd"]
+        );
+        assert_eq!(
+            grid(&names, Some(12), true),
+            vec!["a    bb", "ccc  dddd", "e"]
+        );
+        assert_eq!(
+            grid(&names, Some(1), false),
+            vec!["a", "bb", "ccc", "dddd", "e"]
+        );
+        assert_eq!(grid(&names, None, true), vec!["a  bb  ccc  dddd  e"]);
+    }
+
+    #[test]
+    fn test_grid_many() {
+        let names: Vec<_> = (0..50_000).map(|idx| (format!("f{:05}", idx), 6)).collect();
+
+        // ten columns of six characters and nine gaps fill 78 of the 80
+        let lines = grid(&names, Some(80), false);
+        assert_eq!(lines.len(), 5000);
+        assert_eq!(
+            lines[0],
+            "f00000  f05000  f10000  f15000  f20000  f25000  f30000  f35000  f40000  f45000"
+        );
+        assert_eq!(
+            grid(&names, Some(80), true)[1],
+            "f00010  f00011  f00012  f00013  f00014  f00015  f00016  f00017  f00018  f00019"
+        );
+    }
+}
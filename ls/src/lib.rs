@@ -1,22 +1,46 @@
-use chrono::{DateTime, Local};
-use colored::{ColoredString, Colorize};
-use numfmt::{Formatter, Numeric, Precision, Scales};
+use colored::ColoredString;
+use colors::LsColors;
+use git::GitStatuses;
+use size::Units;
 use std::{
-    fmt::Debug,
-    fs::{self, DirEntry, Metadata},
-    io::Result,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    env,
+    fmt::{self, Debug, Display},
+    fs::{self, Metadata},
+    io::{self, IsTerminal, Result, Write},
     os::{
         linux::fs::MetadataExt,
         unix::fs::{FileTypeExt, PermissionsExt},
     },
-    time::{SystemTime, UNIX_EPOCH},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use time_style::TimeStyle;
+use tree::Node;
+use xattrs::Xattrs;
 
 use clap::Parser;
+use terminal_size::{terminal_size_of, Width};
+use unicode_width::UnicodeWidthStr;
 use users::{get_group_by_gid, get_user_by_uid, Group, User};
 
+mod colors;
+mod du;
+mod git;
+mod grid;
+mod serialize;
+mod size;
+mod time_style;
+mod tree;
+mod xattrs;
+
 #[derive(Parser, Debug)]
+#[command(disable_help_flag = true)]
 pub struct LsCommand {
+    #[arg(long = "help", action = clap::ArgAction::Help, help = "print help")]
+    help: Option<bool>,
+
     #[arg(
         short = 'a',
         long = "all",
@@ -31,18 +55,263 @@ pub struct LsCommand {
     )]
     almost_all: bool,
 
-    #[arg(short = 'l', help = "use a long listing format")]
+    #[arg(
+        short = 'l',
+        help = "use a long listing format",
+        overrides_with_all = ["columns", "across", "one_per_line"]
+    )]
     long_format: bool,
 
     #[arg(
-        short = 'i',
+        short = 'n',
+        long = "numeric-uid-gid",
+        help = "like -l, but list numeric user and group IDs"
+    )]
+    numeric_uid_gid: bool,
+
+    #[arg(short = 'g', help = "like -l, but do not list owner")]
+    no_owner: bool,
+
+    #[arg(short = 'o', help = "like -l, but do not list group information")]
+    no_group: bool,
+
+    #[arg(
+        long = "time-style",
+        value_name = "TIME_STYLE",
+        help = "time/date format with -l: full-iso, long-iso, iso, locale or +FORMAT; TIME_STYLE is used when not given"
+    )]
+    time_style: Option<TimeStyle>,
+
+    #[arg(
+        short = 'u',
+        help = "with -lt: sort by, and show, access time; with -l: show access time and sort by name; otherwise: sort by access time, newest first",
+        overrides_with = "change_time"
+    )]
+    access_time: bool,
+
+    #[arg(
+        short = 'c',
+        help = "with -lt: sort by, and show, ctime (time of last change of file status information); with -l: show ctime and sort by name; otherwise: sort by ctime, newest first",
+        overrides_with = "access_time"
+    )]
+    change_time: bool,
+
+    #[arg(
+        short = 'C',
+        help = "list entries by columns",
+        overrides_with_all = ["long_format", "across", "one_per_line"]
+    )]
+    columns: bool,
+
+    #[arg(
+        short = 'x',
+        help = "list entries by lines instead of by columns",
+        overrides_with_all = ["long_format", "columns", "one_per_line"]
+    )]
+    across: bool,
+
+    #[arg(
+        short = '1',
+        help = "list one file per line",
+        overrides_with_all = ["long_format", "columns", "across"]
+    )]
+    one_per_line: bool,
+
+    #[arg(
+        long = "format",
+        value_name = "WORD",
+        value_parser = ["long", "verbose", "single-column", "vertical", "across", "horizontal", "json", "csv"],
+        help = "across -x, long -l, single-column -1, vertical -C, or json and csv for one record per entry"
+    )]
+    format_word: Option<String>,
+
+    #[arg(
+        short = 'w',
+        long = "width",
+        value_name = "COLS",
+        help = "set output width to COLS. 0 means no limit"
+    )]
+    width: Option<usize>,
+
+    #[arg(
+        short = 'h',
         long = "human-readable",
-        help = "with -l and -s, print sizes like 1K 234M 2G etc."
+        help = "with -l and -s, print sizes like 1K 234M 2G etc.",
+        overrides_with_all = ["si", "block_size"]
     )]
     human_readable: bool,
 
+    #[arg(
+        long = "si",
+        help = "likewise, but use powers of 1000 not 1024",
+        overrides_with_all = ["human_readable", "block_size"]
+    )]
+    si: bool,
+
+    #[arg(
+        long = "block-size",
+        value_name = "SIZE",
+        help = "with -l, scale sizes by SIZE when printing them; e.g., '--block-size=M'",
+        overrides_with_all = ["human_readable", "si"]
+    )]
+    block_size: Option<Units>,
+
+    #[arg(
+        short = 's',
+        long = "size",
+        help = "print the allocated size of each file, in blocks"
+    )]
+    allocated: bool,
+
+    #[arg(
+        short = 'i',
+        long = "inode",
+        help = "print the index number of each file"
+    )]
+    inode: bool,
+
+    #[arg(
+        short = 'Z',
+        long = "context",
+        help = "print the SELinux security context of each file"
+    )]
+    context: bool,
+
+    #[arg(
+        long = "xattrs",
+        help = "list the names and sizes of each file's extended attributes under it"
+    )]
+    xattrs: bool,
+
+    #[arg(
+        short = 't',
+        help = "sort by time, newest first",
+        overrides_with_all = ["sort_by_size", "sort_by_extension", "sort_by_version", "unsorted"]
+    )]
+    sort_by_time: bool,
+
+    #[arg(
+        short = 'S',
+        help = "sort by file size, largest first",
+        overrides_with_all = ["sort_by_time", "sort_by_extension", "sort_by_version", "unsorted"]
+    )]
+    sort_by_size: bool,
+
+    #[arg(
+        short = 'X',
+        help = "sort alphabetically by entry extension",
+        overrides_with_all = ["sort_by_time", "sort_by_size", "sort_by_version", "unsorted"]
+    )]
+    sort_by_extension: bool,
+
+    #[arg(
+        short = 'v',
+        help = "natural sort of (version) numbers within text",
+        overrides_with_all = ["sort_by_time", "sort_by_size", "sort_by_extension", "unsorted"]
+    )]
+    sort_by_version: bool,
+
+    #[arg(
+        short = 'U',
+        help = "do not sort; list entries in directory order",
+        overrides_with_all = ["sort_by_time", "sort_by_size", "sort_by_extension", "sort_by_version"]
+    )]
+    unsorted: bool,
+
+    #[arg(short = 'r', long = "reverse", help = "reverse order while sorting")]
+    reverse: bool,
+
+    #[arg(
+        long = "group-directories-first",
+        help = "group directories before files"
+    )]
+    group_directories_first: bool,
+
+    #[arg(
+        short = 'R',
+        long = "recursive",
+        help = "list subdirectories recursively"
+    )]
+    recursive: bool,
+
+    #[arg(
+        short = 'd',
+        long = "directory",
+        help = "list directories themselves, not their contents"
+    )]
+    directory: bool,
+
+    #[arg(
+        short = 'L',
+        long = "dereference",
+        help = "show information for the file symbolic links point to rather than for the links"
+    )]
+    dereference: bool,
+
+    #[arg(
+        short = 'F',
+        long = "classify",
+        help = "append indicator (one of */=@|) to entries",
+        overrides_with = "slash"
+    )]
+    classify: bool,
+
+    #[arg(
+        short = 'p',
+        help = "append / indicator to directories",
+        overrides_with = "classify"
+    )]
+    slash: bool,
+
+    #[arg(
+        long = "tree",
+        help = "list subdirectories recursively as a tree, with the sizes of directories adding up everything below them"
+    )]
+    tree: bool,
+
+    #[arg(
+        long = "level",
+        value_name = "DEPTH",
+        requires = "tree",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "with --tree, descend at most DEPTH directories"
+    )]
+    level: Option<u64>,
+
+    #[arg(
+        long = "dirs-only",
+        requires = "tree",
+        help = "with --tree, list directories only"
+    )]
+    dirs_only: bool,
+
+    #[arg(
+        long = "du",
+        conflicts_with = "tree",
+        help = "show the disk usage of everything below directories as their size, counting hard links once"
+    )]
+    du: bool,
+
+    #[arg(
+        long = "git",
+        help = "with -l, show the git status of each entry: staged, then unstaged, as one of N M D R T I U or -"
+    )]
+    git: bool,
+
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        default_value = "auto",
+        default_missing_value = "always",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = ["auto", "always", "never"],
+        help = "color the output WHEN; colors are taken from LS_COLORS"
+    )]
+    color: String,
+
     #[arg(value_name = "PATH", default_value = ".")]
-    path: String,
+    paths: Vec<String>,
 }
 
 impl LsCommand {
@@ -54,112 +323,896 @@ impl LsCommand {
         self.all || self.almost_all
     }
 
-    fn process_blocks(total_blocks: Option<u64>, blocks: u64) -> Option<u64> {
-        let total_blocks_so_far = match total_blocks {
-            Some(v) => v,
-            None => 0,
-        };
+    /// A tree has one entry per line, with or without the details of the long format.
+    fn format(&self) -> Format {
+        match self.listing_format() {
+            Format::Long if self.tree => Format::Long,
+            _ if self.tree => Format::OnePerLine,
+            format => format,
+        }
+    }
+
+    /// Columns are only the default when writing to a terminal, like with GNU ls. `--format`
+    /// wins over the options it stands for.
+    fn listing_format(&self) -> Format {
+        match self.format_word.as_deref() {
+            Some("long" | "verbose") => return Format::Long,
+            Some("single-column") => return Format::OnePerLine,
+            Some("vertical") => return Format::Columns,
+            Some("across" | "horizontal") => return Format::Across,
+            Some("json") => return Format::Json,
+            Some("csv") => return Format::Csv,
+            _ => {}
+        }
 
-        // block count is returned in 512 byte blocks but linux counts 1024 size blocks, so divided by 2
-        Some(total_blocks_so_far + blocks / 2)
+        if self.long_format || self.numeric_uid_gid || self.no_owner || self.no_group {
+            Format::Long
+        } else if self.one_per_line {
+            Format::OnePerLine
+        } else if self.across {
+            Format::Across
+        } else if self.columns || io::stdout().is_terminal() {
+            Format::Columns
+        } else {
+            Format::OnePerLine
+        }
     }
 
-    pub fn exec(&self) -> Result<LsOuptut> {
-        let mut dir: Vec<_> = fs::read_dir(&self.path)?.collect();
-        dir.sort_by(|a, b| {
-            a.as_ref()
-                .clone()
-                .unwrap()
-                .file_name()
-                .cmp(&b.as_ref().clone().unwrap().file_name())
-        });
+    /// `-w` wins over `COLUMNS`, which wins over the size of the terminal; 80 columns otherwise.
+    fn line_width(&self) -> Option<usize> {
+        let width = self
+            .width
+            .or_else(|| {
+                env::var("COLUMNS")
+                    .ok()
+                    .and_then(|columns| columns.parse().ok())
+                    .filter(|columns| *columns > 0)
+            })
+            .or_else(|| terminal_size_of(io::stdout()).map(|(Width(width), _)| width.into()))
+            .unwrap_or(80);
+
+        (width > 0).then_some(width)
+    }
 
-        let separator = if self.long_format { "\n" } else { "  " };
+    /// How sizes and then allocated sizes are shown: sizes in bytes and allocations in blocks of
+    /// 1024 bytes, unless -h, --si or --block-size say otherwise for both.
+    fn units(&self) -> (Units, Units) {
+        if self.human_readable {
+            (Units::Human { base: 1024 }, Units::Human { base: 1024 })
+        } else if self.si {
+            (Units::Human { base: 1000 }, Units::Human { base: 1000 })
+        } else if let Some(block_size) = &self.block_size {
+            (block_size.clone(), block_size.clone())
+        } else {
+            let kibibytes = Units::Blocks {
+                size: 1024,
+                suffix: String::new(),
+            };
 
-        let mut total_blocks = None;
-        let mut output = Vec::new();
+            (Units::bytes(), kibibytes)
+        }
+    }
 
-        if self.all {
-            let current_dir_extra = if self.long_format {
-                let metadata = fs::metadata(".")?;
-                total_blocks = Self::process_blocks(total_blocks, metadata.st_blocks());
+    fn time_field(&self) -> TimeField {
+        if self.access_time {
+            TimeField::Accessed
+        } else if self.change_time {
+            TimeField::Changed
+        } else {
+            TimeField::Modified
+        }
+    }
 
-                Some(LsEntryExtra::from(metadata, self.human_readable)?)
-            } else {
-                None
-            };
+    /// `--time-style` wins over `TIME_STYLE`, which is ignored when it isn't a valid style.
+    fn details(&self) -> Details {
+        let time_style = self
+            .time_style
+            .clone()
+            .or_else(|| env::var("TIME_STYLE").ok()?.parse().ok())
+            .unwrap_or(TimeStyle::Locale);
+
+        let (size_units, block_units) = self.units();
+
+        Details {
+            size_units,
+            block_units,
+            inode: self.inode,
+            allocated: self.allocated,
+            context: self.context,
+            xattrs: self.xattrs,
+            dirs_only: self.dirs_only,
+            owner: !self.no_owner,
+            group: !self.no_group,
+            numeric_ids: self.numeric_uid_gid,
+            time_style,
+        }
+    }
+
+    fn indicator_style(&self) -> IndicatorStyle {
+        if self.classify {
+            IndicatorStyle::Classify
+        } else if self.slash {
+            IndicatorStyle::Slash
+        } else {
+            IndicatorStyle::None
+        }
+    }
+
+    /// With -L, what a symbolic link points to, or the link itself when that's gone. A dangling
+    /// link is reported but still listed.
+    fn entry_metadata(&self, path: &Path, metadata: Metadata, result: &mut LsResult) -> Metadata {
+        if !self.dereference || !metadata.is_symlink() {
+            return metadata;
+        }
 
-            let parent_dir_extra = if self.long_format {
-                let metadata = fs::metadata("..")?;
-                total_blocks = Self::process_blocks(total_blocks, metadata.st_blocks());
+        match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                eprintln!(
+                    "ls: cannot access '{}': {}",
+                    path.display(),
+                    io_error_message(&err)
+                );
+                result.exit_code = result.exit_code.max(1);
+                metadata
+            }
+        }
+    }
 
-                Some(LsEntryExtra::from(metadata, self.human_readable)?)
+    /// The last of -t, -S, -X, -v and -U given wins, like with GNU ls.
+    fn sort_by(&self) -> SortBy {
+        if self.unsorted {
+            SortBy::None
+        } else if self.sort_by_time {
+            SortBy::Time
+        } else if self.sort_by_size {
+            SortBy::Size
+        } else if self.sort_by_extension {
+            SortBy::Extension
+        } else if self.sort_by_version {
+            SortBy::Version
+        } else if (self.access_time || self.change_time) && self.format() != Format::Long {
+            SortBy::Time
+        } else {
+            SortBy::Name
+        }
+    }
+
+    fn sort(&self, dir: &Path, files: &mut [(String, Metadata)], usages: &HashMap<String, u64>) {
+        let sort_by = self.sort_by();
+
+        match sort_by {
+            SortBy::None => {}
+            SortBy::Name => files.sort_by(|a, b| a.0.cmp(&b.0)),
+            // newest and largest first, as ls does, with ties broken by name
+            SortBy::Time => {
+                let field = self.time_field();
+                files.sort_by(|a, b| field.of(&b.1).cmp(&field.of(&a.1)).then(a.0.cmp(&b.0)))
+            }
+            SortBy::Size => {
+                let size = |(name, metadata): &(String, Metadata)| {
+                    usages.get(name).copied().unwrap_or(metadata.len())
+                };
+                files.sort_by(|a, b| size(b).cmp(&size(a)).then(a.0.cmp(&b.0)))
+            }
+            SortBy::Extension => {
+                files.sort_by(|a, b| extension(&a.0).cmp(extension(&b.0)).then(a.0.cmp(&b.0)))
+            }
+            SortBy::Version => files.sort_by(|a, b| version_cmp(&a.0, &b.0)),
+        }
+
+        if self.reverse && sort_by != SortBy::None {
+            files.reverse();
+        }
+
+        if self.group_directories_first {
+            // symlinks to directories count as directories here, each link looked up just once
+            // rather than on every comparison; the sort keeps the order within both groups
+            files.sort_by_cached_key(|(name, metadata)| {
+                let is_dir = metadata.is_dir()
+                    || (metadata.is_symlink()
+                        && fs::metadata(dir.join(name)).is_ok_and(|m| m.is_dir()));
+                !is_dir
+            });
+        }
+    }
+
+    /// Files given as operands are listed first, together, then the contents of each directory
+    /// under its own header. Operands that can't be listed are reported and skipped.
+    pub fn exec(&self) -> Result<LsResult> {
+        // `auto` leaves it to colored, which only colors for a terminal
+        let colors = match self.color.as_str() {
+            "never" => LsColors::default(),
+            "always" => {
+                colored::control::set_override(true);
+                LsColors::from_env()
+            }
+            _ => LsColors::from_env(),
+        };
+        let mut result = LsResult::default();
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+
+        if self.tree {
+            for path in &self.paths {
+                match self.tree(path, &colors, &mut result) {
+                    Ok(output) => result.outputs.push(output),
+                    Err(err) => {
+                        eprintln!("ls: cannot access '{}': {}", path, io_error_message(&err));
+                        result.exit_code = 2;
+                    }
+                }
+            }
+
+            return Ok(result);
+        }
+
+        for path in &self.paths {
+            // like GNU ls, a symlink given on the command line is only followed to list the
+            // directory it points to, unless -L has it followed all the time
+            let metadata = if self.dereference {
+                fs::metadata(path)
+            } else if self.directory || self.format().has_details() || self.classify {
+                fs::symlink_metadata(path)
             } else {
-                None
+                fs::metadata(path).or_else(|_| fs::symlink_metadata(path))
             };
 
-            output.push(LsEntry::new(".".blue(), current_dir_extra));
-            output.push(LsEntry::new("..".blue(), parent_dir_extra));
+            match metadata {
+                Ok(metadata) if metadata.is_dir() && !self.directory => {
+                    dirs.push((path.clone(), metadata))
+                }
+                Ok(metadata) => files.push((path.clone(), metadata)),
+                Err(err) => {
+                    eprintln!("ls: cannot access '{}': {}", path, io_error_message(&err));
+                    result.exit_code = 2;
+                }
+            }
         }
 
-        for entry in dir {
-            let entry = entry?;
+        let usages = self.disk_usage(Path::new(""), &files);
+        self.sort(Path::new(""), &mut files, &usages);
+        self.sort(Path::new(""), &mut dirs, &HashMap::new());
+
+        let with_headers = self.recursive || self.paths.len() > 1;
+
+        if !files.is_empty() {
+            result.outputs.push(self.output(
+                None,
+                Path::new(""),
+                files,
+                &usages,
+                false,
+                &colors,
+            )?);
+        }
 
-            let file_or_dir_name = get_colorize_file_name(&entry)?;
+        let mut listed = HashSet::new();
+        for (dir, _) in dirs {
+            let header = with_headers.then(|| dir.clone());
+            let path = PathBuf::from(&dir);
+            if let Err(err) = self.list_dir(header, path, &colors, &mut listed, &mut result) {
+                eprintln!(
+                    "ls: cannot open directory '{}': {}",
+                    dir,
+                    io_error_message(&err)
+                );
+                result.exit_code = 2;
+            }
+        }
 
-            if !self.almost_all() && file_or_dir_name.starts_with(".") {
-                continue;
+        Ok(result)
+    }
+
+    /// Lists a directory and, with -R, every directory below it after it. Trouble with an entry
+    /// or further down is reported as it happens; only the directory itself failing to open is
+    /// returned. With -R, a directory that was already listed, which -L can lead back to, is
+    /// reported and skipped, like GNU ls does.
+    fn list_dir(
+        &self,
+        header: Option<String>,
+        dir: PathBuf,
+        colors: &LsColors,
+        listed: &mut HashSet<(u64, u64)>,
+        result: &mut LsResult,
+    ) -> Result<()> {
+        if self.recursive {
+            let metadata = fs::metadata(&dir)?;
+            if !listed.insert((metadata.st_dev(), metadata.st_ino())) {
+                eprintln!(
+                    "ls: {}: not listing already-listed directory",
+                    dir.display()
+                );
+                result.exit_code = 2;
+                return Ok(());
             }
+        }
 
-            let extra = if self.long_format {
-                let metadata = entry.metadata()?;
-                total_blocks = Self::process_blocks(total_blocks, metadata.st_blocks());
+        let mut files = Vec::new();
 
-                Some(LsEntryExtra::from(metadata, self.human_readable)?)
-            } else {
-                None
+        if self.all {
+            files.push((String::from("."), fs::metadata(&dir)?));
+            files.push((String::from(".."), fs::metadata(dir.join(".."))?));
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    eprintln!(
+                        "ls: reading directory '{}': {}",
+                        dir.display(),
+                        io_error_message(&err)
+                    );
+                    result.exit_code = result.exit_code.max(1);
+                    break;
+                }
+            };
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+
+            if !self.almost_all() && file_name.starts_with('.') {
+                continue;
+            }
+
+            // like GNU ls, names in the current directory are reported without a leading ./
+            let path = match dir == Path::new(".") {
+                true => PathBuf::from(&file_name),
+                false => entry.path(),
+            };
+            // an entry that can't be looked at, say because it went away meanwhile, is reported
+            // and left out
+            let metadata = match entry.metadata() {
+                Ok(metadata) => self.entry_metadata(&path, metadata, result),
+                Err(err) => {
+                    eprintln!(
+                        "ls: cannot access '{}': {}",
+                        path.display(),
+                        io_error_message(&err)
+                    );
+                    result.exit_code = result.exit_code.max(1);
+                    continue;
+                }
             };
+            files.push((file_name, metadata));
+        }
+
+        let usages = self.disk_usage(&dir, &files);
+        self.sort(&dir, &mut files, &usages);
+
+        let subdirs: Vec<PathBuf> = if self.recursive {
+            files
+                .iter()
+                .filter(|(name, metadata)| metadata.is_dir() && name != "." && name != "..")
+                .map(|(name, _)| dir.join(name))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        result
+            .outputs
+            .push(self.output(header, &dir, files, &usages, true, colors)?);
+
+        for subdir in subdirs {
+            let header = subdir.to_string_lossy().into_owned();
+            if let Err(err) = self.list_dir(Some(header.clone()), subdir, colors, listed, result) {
+                eprintln!(
+                    "ls: cannot open directory '{}': {}",
+                    header,
+                    io_error_message(&err)
+                );
+                result.exit_code = result.exit_code.max(1);
+            }
+        }
+
+        Ok(())
+    }
 
-            output.push(LsEntry::new(file_or_dir_name, extra));
+    /// The target is colored like the file it points to would be, or as missing when it's gone.
+    fn link_target(&self, link: &Path, target: PathBuf, colors: &LsColors) -> LinkTarget {
+        let path = target.to_string_lossy().into_owned();
+        let name = quote(path.clone());
+
+        match fs::metadata(link) {
+            Ok(metadata) => LinkTarget {
+                indicator: self.indicator_style().indicator(&metadata),
+                name: colors.paint(name, &target, &metadata),
+                path,
+                broken: false,
+            },
+            Err(_) => LinkTarget {
+                name: colors.paint_missing(name),
+                path,
+                indicator: None,
+                broken: true,
+            },
+        }
+    }
+
+    /// With --du, how much disk the directories among `files` take up, by name. `.` and `..`
+    /// keep their own size, they'd only add up their neighbours again.
+    fn disk_usage(&self, dir: &Path, files: &[(String, Metadata)]) -> HashMap<String, u64> {
+        if !self.du {
+            return HashMap::new();
+        }
+
+        let names: Vec<&String> = files
+            .iter()
+            .filter(|(name, metadata)| metadata.is_dir() && name != "." && name != "..")
+            .map(|(name, _)| name)
+            .collect();
+        let paths: Vec<PathBuf> = names.iter().map(|name| dir.join(name)).collect();
+
+        names
+            .into_iter()
+            .cloned()
+            .zip(du::disk_usage(&paths))
+            .collect()
+    }
+
+    /// Only directory listings get a `total` line, in the long format or with -s. Directories
+    /// are as big as `usages` has them.
+    fn output(
+        &self,
+        header: Option<String>,
+        dir: &Path,
+        files: Vec<(String, Metadata)>,
+        usages: &HashMap<String, u64>,
+        with_total: bool,
+        colors: &LsColors,
+    ) -> Result<LsOuptut> {
+        let mut total_blocks =
+            (with_total && (self.format() == Format::Long || self.allocated)).then_some(0);
+        let mut output = Vec::new();
+        let git = self.git_statuses(dir);
+
+        for (file_name, metadata) in files {
+            total_blocks = total_blocks.map(|total| total + metadata.st_blocks());
+
+            let mut entry = self.entry(dir, file_name, metadata, colors);
+            if let (Some(extra), Some(usage)) = (&mut entry.extra, usages.get(&entry.name)) {
+                extra.size = *usage;
+            }
+            if let Some(git) = &git {
+                entry.git_status = git.status(&dir.join(entry.name()));
+            }
+            output.push(entry);
         }
 
         Ok(LsOuptut::new(
+            header,
+            dir.to_path_buf(),
             total_blocks,
             output,
-            separator,
-            self.human_readable,
+            self.format(),
+            self.line_width(),
+            self.details(),
         ))
     }
+
+    /// Only looked up for the long format, the only one showing them.
+    fn git_statuses(&self, dir: &Path) -> Option<GitStatuses> {
+        if !self.git || self.format() != Format::Long {
+            return None;
+        }
+
+        GitStatuses::new(dir)
+    }
+
+    fn entry(
+        &self,
+        dir: &Path,
+        file_name: String,
+        metadata: Metadata,
+        colors: &LsColors,
+    ) -> LsEntry {
+        let path = dir.join(&file_name);
+        let file_or_dir_name = colors.paint(quote(file_name.clone()), &path, &metadata);
+
+        // the long format shows where a link goes, and what it goes to gets the indicator
+        let link_target = if self.format().has_details() && metadata.is_symlink() {
+            fs::read_link(&path)
+                .ok()
+                .map(|target| self.link_target(&path, target, colors))
+        } else {
+            None
+        };
+        let indicator = match link_target {
+            Some(_) => None,
+            None => self.indicator_style().indicator(&metadata),
+        };
+
+        let inode = metadata.st_ino();
+        let blocks = metadata.st_blocks();
+        let xattrs = if self.format().has_details() || self.context || self.xattrs {
+            Xattrs::read(&path, self.dereference)
+        } else {
+            Xattrs::default()
+        };
+        let extra = self
+            .format()
+            .has_details()
+            .then(|| LsEntryExtra::from(metadata, self.time_field()));
+
+        LsEntry {
+            inode,
+            blocks,
+            xattrs,
+            ..LsEntry::new(file_name, file_or_dir_name, indicator, link_target, extra)
+        }
+    }
+
+    /// Lists `path` and everything below it as a tree, like the `tree` utility. Directories are
+    /// counted as big as everything listed below them, so their sizes stop adding up where
+    /// --level stops the listing.
+    fn tree(&self, path: &str, colors: &LsColors, result: &mut LsResult) -> Result<LsOuptut> {
+        let metadata = fs::metadata(path).or_else(|_| fs::symlink_metadata(path))?;
+        let mut counts = TreeCounts::default();
+
+        let mut size = metadata.len();
+        let children = if metadata.is_dir() {
+            let mut ancestors = vec![(metadata.st_dev(), metadata.st_ino())];
+            let (children, children_size) =
+                self.tree_children(Path::new(path), 1, &mut ancestors, &mut counts, result);
+            size += children_size;
+            children
+        } else {
+            Vec::new()
+        };
+
+        let root = Node {
+            item: (PathBuf::new(), path.to_string(), metadata, size),
+            children,
+        };
+        let git = self.git_statuses(Path::new(path));
+        let entries = tree::flatten(root)
+            .into_iter()
+            .map(|(prefix, (dir, file_name, metadata, size))| {
+                let is_dir = metadata.is_dir();
+                let mut entry = self.entry(&dir, file_name, metadata, colors);
+
+                entry.tree_prefix = prefix;
+                if let Some(git) = &git {
+                    entry.git_status = git.status(&dir.join(entry.name()));
+                }
+                if let Some(extra) = entry.extra.as_mut().filter(|_| is_dir) {
+                    extra.size = size;
+                }
+
+                entry
+            })
+            .collect();
+
+        let mut output = LsOuptut::new(
+            None,
+            PathBuf::new(),
+            None,
+            entries,
+            self.format(),
+            self.line_width(),
+            self.details(),
+        );
+        output.tree_counts = Some(counts);
+
+        Ok(output)
+    }
+
+    /// The nodes for what's in `dir`, with how big they are altogether. Directories already
+    /// being listed further up, which -L can lead back to, aren't gone into again.
+    fn tree_children(
+        &self,
+        dir: &Path,
+        depth: usize,
+        ancestors: &mut Vec<(u64, u64)>,
+        counts: &mut TreeCounts,
+        result: &mut LsResult,
+    ) -> (Vec<Node<TreeItem>>, u64) {
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(err) => {
+                eprintln!(
+                    "ls: cannot open directory '{}': {}",
+                    dir.display(),
+                    io_error_message(&err)
+                );
+                result.exit_code = result.exit_code.max(1);
+                return (Vec::new(), 0);
+            }
+        };
+
+        let mut files = Vec::new();
+        for entry in read_dir.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if !self.almost_all() && file_name.starts_with('.') {
+                continue;
+            }
+
+            if let Ok(metadata) = entry.metadata() {
+                let metadata = self.entry_metadata(&entry.path(), metadata, result);
+                files.push((file_name, metadata));
+            }
+        }
+        self.sort(dir, &mut files, &HashMap::new());
+
+        let mut nodes = Vec::new();
+        let mut total = 0;
+        for (file_name, metadata) in files {
+            let mut size = metadata.len();
+            let mut children = Vec::new();
+
+            if metadata.is_dir() {
+                counts.dirs += 1;
+
+                let id = (metadata.st_dev(), metadata.st_ino());
+                if self.level.is_none_or(|level| (depth as u64) < level) && !ancestors.contains(&id)
+                {
+                    ancestors.push(id);
+                    let (nodes, nodes_size) = self.tree_children(
+                        &dir.join(&file_name),
+                        depth + 1,
+                        ancestors,
+                        counts,
+                        result,
+                    );
+                    ancestors.pop();
+
+                    children = nodes;
+                    size += nodes_size;
+                }
+            } else {
+                counts.files += 1;
+            }
+
+            total += size;
+            if self.dirs_only && !metadata.is_dir() {
+                continue;
+            }
+
+            nodes.push(Node {
+                item: (dir.to_path_buf(), file_name, metadata, size),
+                children,
+            });
+        }
+
+        (nodes, total)
+    }
+}
+
+/// The directory it's in, its name, its metadata and its size along with everything below it.
+type TreeItem = (PathBuf, String, Metadata, u64);
+
+/// How many directories and files a tree has below its root.
+#[derive(Default)]
+struct TreeCounts {
+    dirs: usize,
+    files: usize,
+}
+
+/// Everything `ls` lists, one output per directory, and how it went.
+#[derive(Default)]
+pub struct LsResult {
+    outputs: Vec<LsOuptut>,
+    exit_code: i32,
+}
+
+impl LsResult {
+    /// Writes every listing, with a blank line between two of them. JSON and CSV have no
+    /// listings to tell apart, just one record per entry.
+    pub fn render(&self, out: &mut impl Write) -> Result<()> {
+        match self.outputs.first().map(LsOuptut::format) {
+            Some(Format::Json) => return serialize::json(&self.outputs, out),
+            Some(Format::Csv) => return serialize::csv(&self.outputs, out),
+            _ => {}
+        }
+
+        for (idx, output) in self.outputs.iter().enumerate() {
+            if idx > 0 {
+                writeln!(out)?;
+            }
+            output.render(out)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> &[LsOuptut] {
+        &self.outputs
+    }
+
+    /// 2 when an operand couldn't be listed, 1 when a directory further down couldn't, like GNU
+    /// ls.
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimeField {
+    Modified,
+    Accessed,
+    Changed,
+}
+
+impl TimeField {
+    fn of(self, metadata: &Metadata) -> SystemTime {
+        let (secs, nsecs) = match self {
+            TimeField::Modified => (metadata.st_mtime(), metadata.st_mtime_nsec()),
+            TimeField::Accessed => (metadata.st_atime(), metadata.st_atime_nsec()),
+            TimeField::Changed => (metadata.st_ctime(), metadata.st_ctime_nsec()),
+        };
+        let since_epoch = Duration::new(secs.unsigned_abs(), nsecs as u32);
+
+        if secs < 0 {
+            UNIX_EPOCH - since_epoch
+        } else {
+            UNIX_EPOCH + since_epoch
+        }
+    }
 }
 
-pub struct LsOuptut<'a> {
+/// What listings show besides the names: the fields of the long format, the inode numbers,
+/// allocated sizes and security contexts that can go in front of names in any format, and the
+/// extended attributes that go under them.
+#[derive(Clone, Debug)]
+struct Details {
+    size_units: Units,
+    block_units: Units,
+    inode: bool,
+    allocated: bool,
+    context: bool,
+    xattrs: bool,
+    dirs_only: bool,
+    owner: bool,
+    group: bool,
+    numeric_ids: bool,
+    time_style: TimeStyle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IndicatorStyle {
+    None,
+    Slash,
+    Classify,
+}
+
+impl IndicatorStyle {
+    fn indicator(self, metadata: &Metadata) -> Option<char> {
+        let file_type = metadata.file_type();
+
+        match self {
+            IndicatorStyle::None => None,
+            _ if file_type.is_dir() => Some('/'),
+            IndicatorStyle::Slash => None,
+            IndicatorStyle::Classify if file_type.is_symlink() => Some('@'),
+            IndicatorStyle::Classify if file_type.is_fifo() => Some('|'),
+            IndicatorStyle::Classify if file_type.is_socket() => Some('='),
+            IndicatorStyle::Classify
+                if file_type.is_file() && metadata.permissions().mode() & 0o111 != 0 =>
+            {
+                Some('*')
+            }
+            IndicatorStyle::Classify => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortBy {
+    None,
+    Name,
+    Time,
+    Size,
+    Extension,
+    Version,
+}
+
+/// Everything after the last dot, or nothing for names without one and dotfiles like `.bashrc`.
+fn extension(file_name: &str) -> &str {
+    match file_name.rfind('.') {
+        Some(idx) if idx > 0 => &file_name[idx + 1..],
+        _ => "",
+    }
+}
+
+/// Compares names the way `ls -v` does: runs of digits are compared by their numeric value and
+/// everything in between byte by byte, so `file2` comes before `file10`.
+fn version_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a.as_bytes(), b.as_bytes());
+
+    loop {
+        let a_text = a_rest.iter().take_while(|c| !c.is_ascii_digit()).count();
+        let b_text = b_rest.iter().take_while(|c| !c.is_ascii_digit()).count();
+        let ordering = a_rest[..a_text].cmp(&b_rest[..b_text]);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (a_rest, b_rest) = (&a_rest[a_text..], &b_rest[b_text..]);
+
+        let a_digits = a_rest.iter().take_while(|c| c.is_ascii_digit()).count();
+        let b_digits = b_rest.iter().take_while(|c| c.is_ascii_digit()).count();
+        if a_digits == 0 && b_digits == 0 {
+            return a.cmp(b);
+        }
+
+        // leading zeros don't change the value, after them the longer run is the larger number
+        let a_number = trim_leading_zeros(&a_rest[..a_digits]);
+        let b_number = trim_leading_zeros(&b_rest[..b_digits]);
+        let ordering = a_number
+            .len()
+            .cmp(&b_number.len())
+            .then(a_number.cmp(b_number));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (a_rest, b_rest) = (&a_rest[a_digits..], &b_rest[b_digits..]);
+    }
+}
+
+fn trim_leading_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|c| **c == b'0').count();
+
+    &digits[zeros..]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Long,
+    OnePerLine,
+    Columns,
+    Across,
+    Json,
+    Csv,
+}
+
+impl Format {
+    /// Whether entries come with everything the long format shows.
+    fn has_details(self) -> bool {
+        matches!(self, Format::Long | Format::Json | Format::Csv)
+    }
+}
+
+pub struct LsOuptut {
+    header: Option<String>,
+    dir: PathBuf,
     total_blocks: Option<u64>,
     entries: Vec<LsEntry>,
-    separator: &'a str,
-    human_readable: bool,
-    step_1_formatter: Formatter,
-    step_2_formatter: Formatter,
+    format: Format,
+    line_width: Option<usize>,
+    details: Details,
+    tree_counts: Option<TreeCounts>,
 }
 
-impl<'a> LsOuptut<'a> {
+impl LsOuptut {
     fn new(
+        header: Option<String>,
+        dir: PathBuf,
         total_blocks: Option<u64>,
         entries: Vec<LsEntry>,
-        separator: &'a str,
-        human_readable: bool,
+        format: Format,
+        line_width: Option<usize>,
+        details: Details,
     ) -> Self {
         Self {
+            header,
+            dir,
             total_blocks,
             entries,
-            separator,
-            human_readable,
-            step_1_formatter: Formatter::new()
-                .scales(Scales::new(1024, vec![" K", " M", " G", " T", " P"]).unwrap())
-                .precision(Precision::Significance(4)),
-            step_2_formatter: Formatter::new().precision(Precision::Significance(2)),
+            format,
+            line_width,
+            details,
+            tree_counts: None,
         }
     }
 
+    pub fn render(&self, out: &mut impl Write) -> Result<()> {
+        write!(out, "{}", self)
+    }
+
+    /// The directory listed, when there is more than one listing to tell apart.
+    pub fn header(&self) -> Option<&str> {
+        self.header.as_deref()
+    }
+
     pub fn total_blocks(&self) -> Option<u64> {
         self.total_blocks
     }
@@ -168,57 +1221,244 @@ impl<'a> LsOuptut<'a> {
         &self.entries
     }
 
-    pub fn separator(&self) -> &str {
-        &self.separator
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// The names laid out in columns for `Format::Columns` and `Format::Across`. Widths are
+    /// measured on the names themselves, without the escape codes coloring them.
+    pub fn grid(&self) -> Vec<String> {
+        let cells: Vec<(String, usize)> = self
+            .entries
+            .iter()
+            .zip(self.fields())
+            .map(|(entry, mut text)| {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                let name = entry.file_or_dir_name();
+                let indicator = entry.indicator().map_or(String::new(), String::from);
+                let width = text.len() + UnicodeWidthStr::width(&**name) + indicator.len();
+
+                (format!("{}{}{}", text, name, indicator), width)
+            })
+            .collect();
+
+        grid::grid(&cells, self.line_width, self.format == Format::Across)
     }
 
-    pub fn max_size(&self) -> u64 {
-        let mut max_size = 0;
+    /// Everything shown before each name, one line per entry, in columns as wide as their widest
+    /// field: the inode number and allocated size with -i and -s, then the fields of the long
+    /// format, with the security context after the group when there's one for -Z. Without the long
+    /// format, the context follows the allocated size. Numbers are aligned to the right, owners,
+    /// groups and times to the left. The git status comes last, when there is one.
+    pub fn fields(&self) -> Vec<String> {
+        let now = SystemTime::now();
+        let details = &self.details;
+        let git = self.entries.iter().any(|entry| entry.git_status.is_some());
+        let rows: Vec<Vec<(String, bool)>> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut row = Vec::new();
+                if details.inode {
+                    row.push((entry.inode().to_string(), true));
+                }
+                if details.allocated {
+                    row.push((details.block_units.format(entry.blocks() * 512), true));
+                }
 
-        for entry in &self.entries {
-            if let Some(extra) = entry.extra() {
-                if extra.size() > max_size {
-                    max_size = extra.size();
+                // files without an SELinux context show a question mark, like GNU ls
+                let context = entry.xattrs().context().unwrap_or("?").to_string();
+
+                let Some(extra) = entry.extra() else {
+                    if details.context {
+                        row.push((context, false));
+                    }
+                    return row;
+                };
+                let mut mode = extra.metadata().to_string();
+                mode.extend(entry.xattrs().indicator());
+                row.push((mode, false));
+                row.push((extra.links().to_string(), true));
+                if details.owner {
+                    row.push((extra.owner(details.numeric_ids), false));
+                }
+                if details.group {
+                    row.push((extra.group_name(details.numeric_ids), false));
+                }
+                if details.context {
+                    row.push((context, false));
+                }
+                row.push((extra.size_str(&details.size_units), true));
+                row.push((details.time_style.format(extra.time(), now), false));
+                if git {
+                    row.push((entry.git_status.clone().unwrap_or_default(), false));
                 }
+
+                row
+            })
+            .collect();
+
+        let mut widths = vec![0; rows.first().map_or(0, Vec::len)];
+        for row in &rows {
+            for (width, (field, _)) in widths.iter_mut().zip(row) {
+                *width = (*width).max(UnicodeWidthStr::width(field.as_str()));
             }
         }
 
-        max_size
+        rows.iter()
+            .map(|row| {
+                let fields: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|((field, right), width)| {
+                        let padding = " ".repeat(width - UnicodeWidthStr::width(field.as_str()));
+                        match right {
+                            true => format!("{}{}", padding, field),
+                            false => format!("{}{}", field, padding),
+                        }
+                    })
+                    .collect();
+
+                fields.join(" ")
+            })
+            .collect()
     }
 
+    /// The space the entries take up, in the units allocated sizes are shown in.
     pub fn total_blocks_str(&self) -> Option<String> {
-        if let Some(total_blocks) = self.total_blocks() {
-            return Some(format!(
-                "total {}",
-                if self.human_readable {
-                    format_value(
-                        self.step_1_formatter.clone(),
-                        self.step_2_formatter.clone(),
-                        total_blocks,
-                    )
-                } else {
-                    format!("{}", total_blocks)
+        let total_blocks = self.total_blocks()?;
+
+        Some(format!(
+            "total {}",
+            self.details.block_units.format(total_blocks * 512)
+        ))
+    }
+}
+
+/// A listing the way `ls` prints it: the header and total lines, then the entries in a grid or
+/// one per line.
+impl Display for LsOuptut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(header) = self.header() {
+            writeln!(f, "{}:", header)?;
+        }
+
+        if let Some(total_blocks_str) = self.total_blocks_str() {
+            writeln!(f, "{}", total_blocks_str)?;
+        }
+
+        if matches!(self.format, Format::Columns | Format::Across) {
+            for line in self.grid() {
+                writeln!(f, "{}", line)?;
+            }
+
+            return Ok(());
+        }
+
+        for (entry, fields) in self.entries.iter().zip(self.fields()) {
+            if !fields.is_empty() {
+                write!(f, "{} ", fields)?;
+            }
+
+            write!(f, "{}{}", entry.tree_prefix, entry.file_or_dir_name())?;
+            if let Some(indicator) = entry.indicator() {
+                write!(f, "{}", indicator)?;
+            }
+            if let Some(target) = entry.link_target() {
+                write!(f, " -> {}", target.name())?;
+                if let Some(indicator) = target.indicator() {
+                    write!(f, "{}", indicator)?;
+                }
+            }
+            writeln!(f)?;
+
+            if self.details.xattrs {
+                let attributes = entry.xattrs().attributes();
+                let width = attributes.iter().map(|(name, _)| name.len()).max();
+                for (name, size) in attributes {
+                    writeln!(f, "    {:<2$} {:>6}", name, size, width.unwrap_or(0))?;
                 }
-            ));
+            }
         }
 
-        None
+        // like tree, directories are counted without the root, and files only if listed
+        if let Some(counts) = &self.tree_counts {
+            let plural = |count: usize, one: &str, many: &str| match count {
+                1 => format!("1 {}", one),
+                _ => format!("{} {}", count, many),
+            };
+
+            write!(f, "\n{}", plural(counts.dirs, "directory", "directories"))?;
+            if !self.details.dirs_only {
+                write!(f, ", {}", plural(counts.files, "file", "files"))?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
     }
 }
 
 pub struct LsEntry {
+    name: String,
+    inode: u64,
+    blocks: u64,
+    xattrs: Xattrs,
+    tree_prefix: String,
+    git_status: Option<String>,
     file_or_dir_name: ColoredString,
+    indicator: Option<char>,
+    link_target: Option<LinkTarget>,
     extra: Option<LsEntryExtra>,
 }
 
 impl LsEntry {
-    pub fn new(file_or_dir_name: ColoredString, extra: Option<LsEntryExtra>) -> Self {
+    pub fn new(
+        name: String,
+        file_or_dir_name: ColoredString,
+        indicator: Option<char>,
+        link_target: Option<LinkTarget>,
+        extra: Option<LsEntryExtra>,
+    ) -> Self {
         LsEntry {
+            name,
+            inode: 0,
+            blocks: 0,
+            xattrs: Xattrs::default(),
+            tree_prefix: String::new(),
+            git_status: None,
             file_or_dir_name,
+            indicator,
+            link_target,
             extra,
         }
     }
 
+    pub fn inode(&self) -> u64 {
+        self.inode
+    }
+
+    /// The space allocated to the file, in blocks of 512 bytes.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    pub fn xattrs(&self) -> &Xattrs {
+        &self.xattrs
+    }
+
+    /// What -F or -p appends to the name.
+    pub fn indicator(&self) -> Option<char> {
+        self.indicator
+    }
+
+    /// Where a symbolic link points, in the long format.
+    pub fn link_target(&self) -> Option<&LinkTarget> {
+        self.link_target.as_ref()
+    }
+
     pub fn extra(&self) -> Option<&LsEntryExtra> {
         self.extra.as_ref()
     }
@@ -231,6 +1471,12 @@ impl LsEntry {
         None
     }
 
+    /// The name as it is, without quotes or colors.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name as it's shown.
     pub fn file_or_dir_name(&self) -> &ColoredString {
         &self.file_or_dir_name
     }
@@ -252,72 +1498,136 @@ impl LsEntry {
     }
 }
 
+pub struct LinkTarget {
+    name: ColoredString,
+    path: String,
+    indicator: Option<char>,
+    broken: bool,
+}
+
+impl LinkTarget {
+    pub fn name(&self) -> &ColoredString {
+        &self.name
+    }
+
+    /// The target as the link holds it, without quotes or colors.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn indicator(&self) -> Option<char> {
+        self.indicator
+    }
+
+    /// Whether nothing exists where the link points.
+    pub fn broken(&self) -> bool {
+        self.broken
+    }
+}
+
 pub struct LsEntryExtra {
     metadata: String,
     links: u64,
+    uid: u32,
+    gid: u32,
     user: Option<User>,
     group: Option<Group>,
     size: u64,
+    time: SystemTime,
+    mode: u32,
     modified: SystemTime,
-    human_readable: bool,
-    step_1_formatter: Formatter,
-    step_2_formatter: Formatter,
 }
 
 impl LsEntryExtra {
     pub fn new(
         metadata: String,
         links: u64,
-        user: Option<User>,
-        group: Option<Group>,
+        uid: u32,
+        gid: u32,
         size: u64,
-        modified: SystemTime,
-        human_readable: bool,
+        time: SystemTime,
     ) -> Self {
         LsEntryExtra {
             metadata,
             links,
-            user,
-            group,
+            uid,
+            gid,
+            user: get_user_by_uid(uid),
+            group: get_group_by_gid(gid),
             size,
-            modified,
-            human_readable,
-            step_1_formatter: Formatter::new()
-                .scales(Scales::new(1024, vec!["", " K", " M", " G", " T", " P"]).unwrap())
-                .precision(Precision::Significance(4)),
-            step_2_formatter: Formatter::new().precision(Precision::Significance(2)),
-        }
-    }
-
-    pub fn from(metadata: Metadata, human_readable: bool) -> Result<Self> {
-        Ok(LsEntryExtra::new(
-            Self::get_permissions_string(&metadata),
-            metadata.st_nlink(),
-            get_user_by_uid(metadata.st_uid()),
-            get_group_by_gid(metadata.st_gid()),
-            metadata.st_size(),
-            metadata.modified()?,
-            human_readable,
-        ))
+            time,
+            mode: 0,
+            modified: time,
+        }
+    }
+
+    /// `time` picks which of the file's times the long format shows.
+    fn from(metadata: Metadata, time: TimeField) -> Self {
+        LsEntryExtra {
+            mode: metadata.st_mode() & 0o7777,
+            modified: TimeField::Modified.of(&metadata),
+            ..LsEntryExtra::new(
+                Self::get_permissions_string(&metadata),
+                metadata.st_nlink(),
+                metadata.st_uid(),
+                metadata.st_gid(),
+                metadata.st_size(),
+                time.of(&metadata),
+            )
+        }
     }
+
     fn get_permissions_string(metadata: &Metadata) -> String {
+        let file_type = metadata.file_type();
+        let type_char = if file_type.is_dir() {
+            'd'
+        } else if file_type.is_symlink() {
+            'l'
+        } else if file_type.is_block_device() {
+            'b'
+        } else if file_type.is_char_device() {
+            'c'
+        } else if file_type.is_fifo() {
+            'p'
+        } else if file_type.is_socket() {
+            's'
+        } else {
+            '-'
+        };
+
+        Self::mode_string(type_char, metadata.permissions().mode())
+    }
+
+    fn mode_string(type_char: char, mode: u32) -> String {
         let mut string = String::new();
 
-        string.push(if metadata.is_dir() { 'd' } else { '-' });
+        string.push(type_char);
 
-        let mode = metadata.permissions().mode() % 512;
-        let first_part = mode / 64;
+        let first_part = mode / 64 % 8;
         let second_part = mode % 64 / 8;
         let third_part = mode % 8;
 
-        string.push_str(&Self::get_permissions_substring(first_part));
-        string.push_str(&Self::get_permissions_substring(second_part));
-        string.push_str(&Self::get_permissions_substring(third_part));
+        // setuid, setgid and sticky take the place of the execute bit they go with
+        string.push_str(&Self::get_permissions_substring(
+            first_part,
+            mode & 0o4000 != 0,
+            's',
+        ));
+        string.push_str(&Self::get_permissions_substring(
+            second_part,
+            mode & 0o2000 != 0,
+            's',
+        ));
+        string.push_str(&Self::get_permissions_substring(
+            third_part,
+            mode & 0o1000 != 0,
+            't',
+        ));
 
         string
     }
 
-    fn get_permissions_substring(mode_part: u32) -> String {
+    fn get_permissions_substring(mode_part: u32, special: bool, special_char: char) -> String {
         let mut output = String::new();
 
         if mode_part & (1 << 2) > 0 {
@@ -332,7 +1642,12 @@ impl LsEntryExtra {
             output.push('-');
         }
 
-        if mode_part & 1 > 0 {
+        let executable = mode_part & 1 > 0;
+        if special && executable {
+            output.push(special_char);
+        } else if special {
+            output.push(special_char.to_ascii_uppercase());
+        } else if executable {
             output.push('x');
         } else {
             output.push('-');
@@ -349,6 +1664,32 @@ impl LsEntryExtra {
         self.links
     }
 
+    /// What kind of file this is, spelled out.
+    pub fn file_type(&self) -> &'static str {
+        match self.metadata.chars().next() {
+            Some('d') => "directory",
+            Some('l') => "symlink",
+            Some('b') => "block_device",
+            Some('c') => "char_device",
+            Some('p') => "fifo",
+            Some('s') => "socket",
+            _ => "file",
+        }
+    }
+
+    /// The permission bits along with setuid, setgid and sticky, as a number.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
     pub fn user(&self) -> Option<&User> {
         self.user.as_ref()
     }
@@ -357,93 +1698,380 @@ impl LsEntryExtra {
         self.group.as_ref()
     }
 
+    /// The owner's name, or their id when asked for or when there is no name for it.
+    pub fn owner(&self, numeric: bool) -> String {
+        match &self.user {
+            Some(user) if !numeric => user.name().to_string_lossy().into_owned(),
+            _ => self.uid.to_string(),
+        }
+    }
+
+    /// The group's name, or its id when asked for or when there is no name for it.
+    pub fn group_name(&self, numeric: bool) -> String {
+        match &self.group {
+            Some(group) if !numeric => group.name().to_string_lossy().into_owned(),
+            _ => self.gid.to_string(),
+        }
+    }
+
     pub fn size(&self) -> u64 {
         self.size
     }
 
+    /// The time shown in the long format: modification time by default, or the access or
+    /// status change time with -u or -c.
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
     pub fn modified(&self) -> SystemTime {
         self.modified
     }
 
-    pub fn size_str(&self, max_str_len: usize) -> String {
-        let mut size_str = String::new();
+    pub fn size_str(&self, units: &Units) -> String {
+        units.format(self.size)
+    }
+}
 
-        let size_str_val = if self.human_readable && self.size() > 1024 {
-            format_value(
-                self.step_1_formatter.clone(),
-                self.step_2_formatter.clone(),
-                self.size(),
-            )
-        } else {
-            format!("{}", self.size())
-        };
+fn quote(file_name: String) -> String {
+    if file_name.contains(" ") {
+        format!("'{}'", file_name)
+    } else {
+        file_name
+    }
+}
+
+/// `io::Error`'s `Display` appends "(os error N)", which ls's messages never carry.
+fn io_error_message(err: &io::Error) -> String {
+    let message = err.to_string();
 
-        let num_spaces_needed = max_str_len.saturating_sub(size_str_val.len());
-        for _ in 0..num_spaces_needed {
-            size_str.push(' ');
+    match message.find(" (os error ") {
+        Some(idx) => String::from(&message[..idx]),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extension, version_cmp, LsCommand, LsEntryExtra};
+    use clap::Parser;
+    use filetime::FileTime;
+    use std::{
+        cmp::Ordering,
+        fs::{self, Permissions},
+        os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+    use tempfile::TempDir;
+
+    /// Empty files and links with fixed times and modes, so nothing in a listing depends on the
+    /// file system or when the tests run. Directories are left out of `files`, as their sizes
+    /// vary between file systems.
+    fn fixture() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let files = root.join("files");
+
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::create_dir(&files).unwrap();
+        for (name, mode) in [("a.txt", 0o644), ("run.sh", 0o755), ("with space", 0o600)] {
+            fs::write(files.join(name), "").unwrap();
+            fs::set_permissions(files.join(name), Permissions::from_mode(mode)).unwrap();
+        }
+        symlink("a.txt", files.join("link")).unwrap();
+        symlink("missing", files.join("broken")).unwrap();
+
+        for (idx, name) in ["a.txt", "broken", "link", "run.sh", "with space"]
+            .iter()
+            .enumerate()
+        {
+            let time = FileTime::from_unix_time(1_700_000_000 + idx as i64 * 60, 0);
+            filetime::set_symlink_file_times(files.join(name), time, time).unwrap();
         }
-        size_str.push_str(&size_str_val);
 
-        size_str
+        dir
     }
 
-    pub fn modified_str(&self) -> String {
-        let mut modified_str = String::new();
+    fn ls(args: &[&str], dir: &Path) -> String {
+        let dir = dir.to_str().unwrap();
+        let cmd = LsCommand::try_parse_from(
+            ["ls", "--color=never"]
+                .iter()
+                .chain(args)
+                .map(|arg| arg.replace("$DIR", dir)),
+        )
+        .unwrap();
 
-        let seconds = self.modified().duration_since(UNIX_EPOCH).unwrap();
-        let datetime = DateTime::from_timestamp(seconds.as_secs().try_into().unwrap(), 0)
-            .unwrap()
-            .naive_utc();
-        let datetime =
-            DateTime::<Local>::from_naive_utc_and_offset(datetime, Local::now().offset().clone());
-        let datetime = datetime.format("%b %e %H:%M");
-        modified_str.push_str(&format!("{}", datetime));
+        let mut out = Vec::new();
+        cmd.exec().unwrap().render(&mut out).unwrap();
 
-        modified_str
+        String::from_utf8(out).unwrap().replace(dir, "$DIR")
     }
-}
 
-fn format_value<T: Numeric>(
-    mut step_1_formatter: Formatter,
-    mut step_2_formatter: Formatter,
-    value: T,
-) -> String {
-    let formatted_size = step_1_formatter.fmt2(value);
-
-    let split = formatted_size.split(" ");
-    let mut num_str = "";
-    let mut unit_str = "";
-    for (idx, part) in split.into_iter().enumerate() {
-        if idx == 0 {
-            num_str = part;
-        } else {
-            unit_str = part;
+    #[test]
+    fn test_render_long() {
+        let dir = fixture();
+
+        assert_eq!(
+            ls(&["-goF", "--time-style=+%s", "$DIR/files"], dir.path()),
+            "\
+total 0
+-rw-r--r-- 1 0 1700000000 a.txt
+lrwxrwxrwx 1 7 1700000060 broken -> missing
+lrwxrwxrwx 1 5 1700000120 link -> a.txt
+-rwxr-xr-x 1 0 1700000180 run.sh*
+-rw------- 1 0 1700000240 'with space'
+"
+        );
+        assert_eq!(
+            ls(
+                &[
+                    "-got",
+                    "--time-style=+%s",
+                    "$DIR/files/link",
+                    "$DIR/files/run.sh"
+                ],
+                dir.path()
+            ),
+            "\
+-rwxr-xr-x 1 0 1700000180 $DIR/files/run.sh
+lrwxrwxrwx 1 5 1700000120 $DIR/files/link -> a.txt
+"
+        );
+    }
+
+    #[test]
+    fn test_render_sizes() {
+        let dir = fixture();
+        let big = dir.path().join("dir/big");
+        fs::write(&big, vec![0; 1536]).unwrap();
+        let ino = fs::metadata(&big).unwrap().ino();
+
+        assert_eq!(
+            ls(&["-goh", "--time-style=+", "$DIR/dir/big"], dir.path()),
+            "-rw-r--r-- 1 1.5K  $DIR/dir/big\n"
+        );
+        assert_eq!(
+            ls(
+                &["-go", "--si", "--time-style=+", "$DIR/dir/big"],
+                dir.path()
+            ),
+            "-rw-r--r-- 1 1.6k  $DIR/dir/big\n"
+        );
+        assert_eq!(
+            ls(
+                &["-go", "--block-size=K", "--time-style=+", "$DIR/dir/big"],
+                dir.path()
+            ),
+            "-rw-r--r-- 1 2K  $DIR/dir/big\n"
+        );
+        assert_eq!(
+            ls(&["-s", "-x", "$DIR/files"], dir.path()),
+            "total 0\n0 a.txt  0 broken  0 link  0 run.sh  0 'with space'\n"
+        );
+        assert_eq!(
+            ls(&["-i", "$DIR/dir/big"], dir.path()),
+            format!("{} $DIR/dir/big\n", ino)
+        );
+    }
+
+    #[test]
+    fn test_render_xattrs() {
+        let dir = fixture();
+        // not every file system takes user attributes
+        if xattr::set(dir.path().join("files/a.txt"), "user.origin", b"fixture").is_err() {
+            return;
         }
+
+        assert_eq!(
+            ls(
+                &[
+                    "-go",
+                    "--xattrs",
+                    "--time-style=+%s",
+                    "$DIR/files/a.txt",
+                    "$DIR/files/run.sh"
+                ],
+                dir.path()
+            ),
+            "\
+-rw-r--r--@ 1 0 1700000000 $DIR/files/a.txt
+    user.origin      7
+-rwxr-xr-x  1 0 1700000180 $DIR/files/run.sh
+"
+        );
     }
-    let size: f64 = num_str.parse().unwrap();
-    let size = if size < 10.0 {
-        (size * 10.0).ceil() / 10.0
-    } else {
-        size.ceil()
-    };
 
-    format!("{}{}", step_2_formatter.fmt2(size), unit_str,)
-}
+    #[test]
+    fn test_render_grid() {
+        let dir = fixture();
+
+        assert_eq!(
+            ls(&["-C", "-w", "30", "-p", "$DIR"], dir.path()),
+            "dir/  files/\n"
+        );
+        assert_eq!(
+            ls(&["-x", "-w", "30", "-F", "$DIR/files"], dir.path()),
+            "\
+a.txt    broken@       link@
+run.sh*  'with space'
+"
+        );
+        assert_eq!(
+            ls(&["-1R", "$DIR"], dir.path()),
+            "\
+$DIR:
+dir
+files
+
+$DIR/dir:
+
+$DIR/files:
+a.txt
+broken
+link
+run.sh
+'with space'
+"
+        );
+    }
 
-fn get_colorize_file_name(entry: &DirEntry) -> Result<ColoredString> {
-    let mut file_name = entry.file_name().into_string().unwrap();
-    if file_name.contains(" ") {
-        file_name = format!("'{}'", file_name);
+    #[test]
+    fn test_render_tree() {
+        let dir = fixture();
+
+        assert_eq!(
+            ls(&["--tree", "-F", "$DIR"], dir.path()),
+            "\
+$DIR/
+├── dir/
+└── files/
+    ├── a.txt
+    ├── broken@
+    ├── link@
+    ├── run.sh*
+    └── 'with space'
+
+2 directories, 5 files
+"
+        );
+        assert_eq!(
+            ls(
+                &["--tree", "--dirs-only", "--level", "1", "$DIR"],
+                dir.path()
+            ),
+            "\
+$DIR
+├── dir
+└── files
+
+2 directories
+"
+        );
     }
-    let file_type = entry.file_type()?;
 
-    if file_type.is_dir() {
-        Ok(file_name.blue())
-    } else if file_type.is_symlink() {
-        Ok(file_name.cyan())
-    } else if file_type.is_block_device() || file_type.is_char_device() {
-        Ok(file_name.yellow().on_black())
-    } else {
-        Ok(file_name.white())
+    #[test]
+    fn test_recursive_loop() {
+        let dir = fixture();
+        symlink("..", dir.path().join("dir/up")).unwrap();
+
+        // the link back up is listed once, then its way back into dir is reported and skipped
+        assert_eq!(
+            ls(&["-RL", "$DIR/dir"], dir.path()),
+            "\
+$DIR/dir:
+up
+
+$DIR/dir/up:
+dir
+files
+
+$DIR/dir/up/files:
+a.txt
+broken
+link
+run.sh
+'with space'
+"
+        );
+
+        let cmd = LsCommand::try_parse_from(["ls", "-RL", dir.path().to_str().unwrap()]).unwrap();
+        assert_eq!(cmd.exec().unwrap().exit_code(), 2);
+    }
+
+    #[test]
+    fn test_render_json_csv() {
+        let dir = fixture();
+
+        let json = ls(&["--format=json", "$DIR/files"], dir.path());
+        let records: Vec<serde_json::Value> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[1]["name"], "broken");
+        assert_eq!(records[1]["path"], "$DIR/files/broken");
+        assert_eq!(records[1]["type"], "symlink");
+        assert_eq!(records[1]["target"], "missing");
+        assert_eq!(records[3]["mode"], "0755");
+        assert_eq!(records[3]["mtime"], "2023-11-14T22:16:20Z");
+        assert_eq!(records[4]["name"], "with space");
+        assert_eq!(records[4]["target"], serde_json::Value::Null);
+        assert!(records[4]["inode"].as_u64().unwrap() > 0);
+
+        let csv = ls(&["--format=csv", "$DIR/files/link"], dir.path());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "name,path,type,mode,links,uid,owner,gid,group,size,mtime,target,inode"
+        );
+        assert!(lines[1].starts_with("$DIR/files/link,$DIR/files/link,symlink,0777,1,"));
+        assert!(lines[1].contains(",5,2023-11-14T22:15:20Z,a.txt,"));
+    }
+
+    #[test]
+    fn test_unknown_ids() {
+        let extra = LsEntryExtra::new(
+            String::from("-rw-r--r--"),
+            1,
+            4_000_000_000,
+            4_000_000_001,
+            0,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        );
+
+        assert_eq!(extra.owner(false), "4000000000");
+        assert_eq!(extra.group_name(false), "4000000001");
+    }
+
+    #[test]
+    fn test_version_cmp() {
+        let mut names = vec![
+            "file10", "file2", "file1.10", "file1.9", "file", "file02", "a",
+        ];
+        names.sort_by(|a, b| version_cmp(a, b));
+
+        assert_eq!(
+            names,
+            vec!["a", "file", "file1.9", "file1.10", "file02", "file2", "file10"]
+        );
+        assert_eq!(version_cmp("v1.2", "v1.2"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_mode_string() {
+        assert_eq!(LsEntryExtra::mode_string('-', 0o644), "-rw-r--r--");
+        assert_eq!(LsEntryExtra::mode_string('-', 0o4755), "-rwsr-xr-x");
+        assert_eq!(LsEntryExtra::mode_string('-', 0o2644), "-rw-r-Sr--");
+        assert_eq!(LsEntryExtra::mode_string('d', 0o1777), "drwxrwxrwt");
+        assert_eq!(LsEntryExtra::mode_string('d', 0o1770), "drwxrwx--T");
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("archive.tar.gz"), "gz");
+        assert_eq!(extension(".bashrc"), "");
+        assert_eq!(extension("Makefile"), "");
     }
 }
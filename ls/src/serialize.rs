@@ -0,0 +1,84 @@
+use std::io::{Result, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use crate::{LsEntry, LsOuptut};
+
+const FIELDS: [&str; 13] = [
+    "name", "path", "type", "mode", "links", "uid", "owner", "gid", "group", "size", "mtime",
+    "target", "inode",
+];
+
+/// One JSON object per line and entry, like `find --json` writes.
+pub fn json(outputs: &[LsOuptut], out: &mut impl Write) -> Result<()> {
+    for (output, entry) in entries(outputs) {
+        let fields: Vec<String> = FIELDS
+            .iter()
+            .zip(record(output, entry))
+            .map(|(field, value)| format!("{}:{}", json!(field), value))
+            .collect();
+
+        writeln!(out, "{{{}}}", fields.join(","))?;
+    }
+
+    Ok(())
+}
+
+/// A header line naming the fields, then one line per entry, quoted as RFC 4180 has it.
+pub fn csv(outputs: &[LsOuptut], out: &mut impl Write) -> Result<()> {
+    writeln!(out, "{}", FIELDS.join(","))?;
+
+    for (output, entry) in entries(outputs) {
+        let fields: Vec<String> = record(output, entry)
+            .into_iter()
+            .map(|value| match value {
+                Value::Null => String::new(),
+                Value::String(field) if field.contains([',', '"', '\n', '\r']) => {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                }
+                Value::String(field) => field,
+                value => value.to_string(),
+            })
+            .collect();
+
+        writeln!(out, "{}", fields.join(","))?;
+    }
+
+    Ok(())
+}
+
+fn entries(outputs: &[LsOuptut]) -> impl Iterator<Item = (&LsOuptut, &LsEntry)> {
+    outputs
+        .iter()
+        .flat_map(|output| output.entries().iter().map(move |entry| (output, entry)))
+}
+
+/// The values of `FIELDS` for an entry, with times in UTC.
+fn record(output: &LsOuptut, entry: &LsEntry) -> Vec<Value> {
+    let path = output.dir.join(entry.name());
+    let target = entry.link_target().map(|target| target.path());
+    let mut record = vec![json!(entry.name()), json!(path.to_string_lossy())];
+
+    match entry.extra() {
+        Some(extra) => record.extend([
+            json!(extra.file_type()),
+            json!(format!("{:04o}", extra.mode())),
+            json!(extra.links()),
+            json!(extra.uid()),
+            json!(extra.owner(false)),
+            json!(extra.gid()),
+            json!(extra.group_name(false)),
+            json!(extra.size()),
+            json!(
+                DateTime::<Utc>::from(extra.modified()).to_rfc3339_opts(SecondsFormat::Secs, true)
+            ),
+            json!(target),
+            json!(entry.inode()),
+        ]),
+        // JSON and CSV listings always come with details, this only keeps the record whole
+        None => record.resize(FIELDS.len(), Value::Null),
+    }
+
+    record
+}
@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+/// How sizes are shown: scaled like `-h` and `--si` have them, or counted in blocks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Units {
+    /// A few digits and a suffix, in powers of 1024 or 1000.
+    Human { base: u64 },
+    /// A number of blocks of `size` bytes, rounded up, followed by `suffix`.
+    Blocks { size: u64, suffix: String },
+}
+
+impl Units {
+    pub fn bytes() -> Self {
+        Units::Blocks {
+            size: 1,
+            suffix: String::new(),
+        }
+    }
+
+    pub fn format(&self, bytes: u64) -> String {
+        match self {
+            Units::Human { base } => human(bytes, *base),
+            Units::Blocks { size, suffix } => format!("{}{}", bytes.div_ceil(*size), suffix),
+        }
+    }
+}
+
+/// `--block-size` takes a number of bytes, a unit like `K` or `MB`, or both, like GNU ls. Given
+/// without a number, the unit is shown after each size.
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid block size '{}'", spec);
+
+        let digits = spec.bytes().take_while(u8::is_ascii_digit).count();
+        let (number, unit) = spec.split_at(digits);
+        let count: u64 = match number {
+            "" => 1,
+            number => number.parse().map_err(|_| invalid())?,
+        };
+
+        let multiplier = match unit {
+            "" => 1,
+            _ => {
+                let (power, base) = match unit.as_bytes() {
+                    [letter] => (*letter, 1024),
+                    [letter, b'B'] => (*letter, 1000),
+                    [letter, b'i', b'B'] => (*letter, 1024),
+                    _ => return Err(invalid()),
+                };
+                let exponent = b"KMGTPE"
+                    .iter()
+                    .position(|c| *c == power.to_ascii_uppercase())
+                    .ok_or_else(invalid)?;
+
+                u64::checked_pow(base, exponent as u32 + 1).ok_or_else(invalid)?
+            }
+        };
+
+        let size = count.checked_mul(multiplier).ok_or_else(invalid)?;
+        if size == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Units::Blocks {
+            size,
+            suffix: match number {
+                "" => unit.to_string(),
+                _ => String::new(),
+            },
+        })
+    }
+}
+
+/// Scales `bytes` down the way GNU ls does for `-h` and `--si`: sizes under the base are shown as
+/// they are, anything else rounded up to one decimal below 10 and to a whole number above, so
+/// that a size is never shown smaller than it is.
+fn human(bytes: u64, base: u64) -> String {
+    const SUFFIXES: &[u8] = b"KMGTPE";
+
+    if bytes < base {
+        return bytes.to_string();
+    }
+
+    // like GNU's human.c, what's divided away is kept as tenths and as whether the rest of it is
+    // nothing (0), less than half (1), half (2) or more than half (3) of a tenth
+    let mut amount = bytes;
+    let mut tenths = 0;
+    let mut rounding = 0;
+    let mut exponent = 0;
+
+    while amount >= base && exponent < SUFFIXES.len() {
+        let r10 = (amount % base) * 10 + tenths;
+        let r2 = (r10 % base) * 2 + (rounding >> 1);
+        amount /= base;
+        tenths = r10 / base;
+        rounding = if r2 < base {
+            u64::from(r2 + rounding != 0)
+        } else {
+            2 + u64::from(base < r2 + rounding)
+        };
+        exponent += 1;
+    }
+
+    let suffix = |exponent: usize| match (exponent, base) {
+        (1, 1000) => 'k',
+        _ => SUFFIXES[exponent - 1] as char,
+    };
+
+    if amount < 10 {
+        if rounding > 0 {
+            tenths += 1;
+            rounding = 0;
+            if tenths == 10 {
+                amount += 1;
+                tenths = 0;
+            }
+        }
+
+        if amount < 10 {
+            return format!("{}.{}{}", amount, tenths, suffix(exponent));
+        }
+    }
+
+    if tenths + rounding > 0 {
+        amount += 1;
+
+        // rounding up can reach the next unit, which is then shown as 1.0 of it
+        if amount == base && exponent < SUFFIXES.len() {
+            return format!("1.0{}", suffix(exponent + 1));
+        }
+    }
+
+    format!("{}{}", amount, suffix(exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{human, Units};
+
+    #[test]
+    fn test_human() {
+        assert_eq!(human(0, 1024), "0");
+        assert_eq!(human(1023, 1024), "1023");
+        assert_eq!(human(1024, 1024), "1.0K");
+        assert_eq!(human(1025, 1024), "1.1K");
+        assert_eq!(human(1536, 1024), "1.5K");
+        assert_eq!(human(10 * 1024 - 1, 1024), "10K");
+        assert_eq!(human(10 * 1024, 1024), "10K");
+        assert_eq!(human(10 * 1024 + 1, 1024), "11K");
+        assert_eq!(human(1024 * 1024 - 1, 1024), "1.0M");
+        assert_eq!(human(5 * 1024 * 1024 + 1, 1024), "5.1M");
+        assert_eq!(human(1_048_576 * 1024, 1024), "1.0G");
+        assert_eq!(human(u64::MAX, 1024), "16E");
+        assert_eq!(human(999, 1000), "999");
+        assert_eq!(human(1000, 1000), "1.0k");
+        assert_eq!(human(4096, 1000), "4.1k");
+        assert_eq!(human(999_999, 1000), "1.0M");
+        assert_eq!(human(123_456_789, 1000), "124M");
+    }
+
+    #[test]
+    fn test_block_size() {
+        let blocks = |spec: &str| spec.parse::<Units>().map(|units| units.format(5000));
+
+        assert_eq!(blocks("K"), Ok(String::from("5K")));
+        assert_eq!(blocks("1K"), Ok(String::from("5")));
+        assert_eq!(blocks("KB"), Ok(String::from("5KB")));
+        assert_eq!(blocks("KiB"), Ok(String::from("5KiB")));
+        assert_eq!(blocks("M"), Ok(String::from("1M")));
+        assert_eq!(blocks("512"), Ok(String::from("10")));
+        assert_eq!(blocks("1"), Ok(String::from("5000")));
+        assert!(blocks("0").is_err());
+        assert!(blocks("X").is_err());
+        assert!(blocks("KBB").is_err());
+        assert!(blocks("99999999999E").is_err());
+    }
+}
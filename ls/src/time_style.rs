@@ -0,0 +1,125 @@
+use std::{fmt::Display, str::FromStr, time::SystemTime};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local, TimeDelta, TimeZone,
+};
+
+/// Half of an average Gregorian year, which is how far back GNU ls counts a time as recent.
+const SIX_MONTHS: TimeDelta = TimeDelta::seconds(31_556_952 / 2);
+
+/// How times are shown in the long format, as picked with `--time-style` or `TIME_STYLE`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeStyle {
+    /// `Oct 19 00:47`, or `Oct 19  2023` for times that aren't recent.
+    Locale,
+    FullIso,
+    LongIso,
+    /// `10-19 00:47`, or `2023-10-19 ` for times that aren't recent.
+    Iso,
+    /// strftime formats for times that aren't recent and for those that are.
+    Format {
+        old: String,
+        recent: String,
+    },
+}
+
+impl FromStr for TimeStyle {
+    type Err = String;
+
+    /// `+FORMAT` takes one format for every time, or `+OLD\nRECENT` two, like GNU ls.
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        if let Some(format) = style.strip_prefix('+') {
+            let (old, recent) = format.split_once('\n').unwrap_or((format, format));
+            for format in [old, recent] {
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    return Err(format!("invalid time format '{}'", format));
+                }
+            }
+
+            return Ok(TimeStyle::Format {
+                old: old.to_string(),
+                recent: recent.to_string(),
+            });
+        }
+
+        match style {
+            "locale" => Ok(TimeStyle::Locale),
+            "full-iso" => Ok(TimeStyle::FullIso),
+            "long-iso" => Ok(TimeStyle::LongIso),
+            "iso" => Ok(TimeStyle::Iso),
+            _ => Err(format!(
+                "invalid time style '{}'; expected full-iso, long-iso, iso, locale or +FORMAT",
+                style
+            )),
+        }
+    }
+}
+
+impl TimeStyle {
+    pub fn format(&self, time: SystemTime, now: SystemTime) -> String {
+        self.format_in(DateTime::<Local>::from(time), DateTime::<Local>::from(now))
+    }
+
+    /// A time is recent when it's less than six months old and not in the future.
+    fn format_in<Tz: TimeZone>(&self, time: DateTime<Tz>, now: DateTime<Tz>) -> String
+    where
+        Tz::Offset: Display,
+    {
+        let recent = time <= now && now.clone() - time.clone() < SIX_MONTHS;
+        let format = match (self, recent) {
+            (TimeStyle::Locale, true) => "%b %e %H:%M",
+            (TimeStyle::Locale, false) => "%b %e  %Y",
+            (TimeStyle::FullIso, _) => "%Y-%m-%d %H:%M:%S%.9f %z",
+            (TimeStyle::LongIso, _) => "%Y-%m-%d %H:%M",
+            (TimeStyle::Iso, true) => "%m-%d %H:%M",
+            (TimeStyle::Iso, false) => "%Y-%m-%d ",
+            (TimeStyle::Format { recent: format, .. }, true) => format,
+            (TimeStyle::Format { old: format, .. }, false) => format,
+        };
+
+        time.format(format).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::TimeStyle;
+
+    #[test]
+    fn test_format() {
+        let now = Utc.with_ymd_and_hms(2024, 10, 19, 12, 0, 0).unwrap();
+        let recent = Utc.with_ymd_and_hms(2024, 6, 1, 8, 5, 0).unwrap();
+        let old = Utc.with_ymd_and_hms(2024, 3, 1, 8, 5, 0).unwrap();
+        let future = Utc.with_ymd_and_hms(2024, 10, 20, 8, 5, 0).unwrap();
+
+        let locale = TimeStyle::Locale;
+        assert_eq!(locale.format_in(recent, now), "Jun  1 08:05");
+        assert_eq!(locale.format_in(old, now), "Mar  1  2024");
+        assert_eq!(locale.format_in(future, now), "Oct 20  2024");
+
+        let iso: TimeStyle = "iso".parse().unwrap();
+        assert_eq!(iso.format_in(recent, now), "06-01 08:05");
+        assert_eq!(iso.format_in(old, now), "2024-03-01 ");
+
+        let long_iso: TimeStyle = "long-iso".parse().unwrap();
+        assert_eq!(long_iso.format_in(old, now), "2024-03-01 08:05");
+
+        let full_iso: TimeStyle = "full-iso".parse().unwrap();
+        assert_eq!(
+            full_iso.format_in(recent, now),
+            "2024-06-01 08:05:00.000000000 +0000"
+        );
+
+        let custom: TimeStyle = "+%Y\n%H:%M".parse().unwrap();
+        assert_eq!(custom.format_in(recent, now), "08:05");
+        assert_eq!(custom.format_in(old, now), "2024");
+
+        assert!("posix".parse::<TimeStyle>().is_err());
+        assert!("+%Q".parse::<TimeStyle>().is_err());
+        assert!("+%Y-%m-%d %".parse::<TimeStyle>().is_err());
+        assert!("+%Y\n%H:%Q".parse::<TimeStyle>().is_err());
+    }
+}
@@ -0,0 +1,70 @@
+/// Something shown in a tree along with what's below it.
+pub struct Node<T> {
+    pub item: T,
+    pub children: Vec<Node<T>>,
+}
+
+/// Lists a tree from the root down, each item after the box-drawing connectors that go in front
+/// of it, like the `tree` utility draws them. The root goes first without any.
+pub fn flatten<T>(root: Node<T>) -> Vec<(String, T)> {
+    let mut lines = vec![(String::new(), root.item)];
+    flatten_children(root.children, &mut String::new(), &mut lines);
+
+    lines
+}
+
+fn flatten_children<T>(children: Vec<Node<T>>, indent: &mut String, lines: &mut Vec<(String, T)>) {
+    let count = children.len();
+
+    for (idx, child) in children.into_iter().enumerate() {
+        let last = idx + 1 == count;
+        let connector = if last { "└── " } else { "├── " };
+        lines.push((format!("{}{}", indent, connector), child.item));
+
+        let len = indent.len();
+        indent.push_str(if last { "    " } else { "│   " });
+        flatten_children(child.children, indent, lines);
+        indent.truncate(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flatten, Node};
+
+    fn node(item: &'static str, children: Vec<Node<&'static str>>) -> Node<&'static str> {
+        Node { item, children }
+    }
+
+    #[test]
+    fn test_flatten() {
+        let root = node(
+            ".",
+            vec![
+                node(
+                    "a",
+                    vec![node("a1", vec![]), node("a2", vec![node("x", vec![])])],
+                ),
+                node("b", vec![node("b1", vec![])]),
+            ],
+        );
+
+        let lines: Vec<String> = flatten(root)
+            .into_iter()
+            .map(|(prefix, item)| format!("{}{}", prefix, item))
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                ".",
+                "├── a",
+                "│   ├── a1",
+                "│   └── a2",
+                "│       └── x",
+                "└── b",
+                "    └── b1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::{ffi::OsStr, path::Path};
+
+const ACL_ACCESS: &str = "system.posix_acl_access";
+const ACL_DEFAULT: &str = "system.posix_acl_default";
+const SELINUX: &str = "security.selinux";
+
+/// The extended attributes of a file, by name and the size of their values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Xattrs {
+    attributes: Vec<(String, usize)>,
+    context: Option<String>,
+}
+
+impl Xattrs {
+    /// Reads the attributes of `path`, or of what it links to when `dereference` is set. A file
+    /// system without extended attributes gives none, as do files that can't be read.
+    pub fn read(path: &Path, dereference: bool) -> Self {
+        let names = match dereference {
+            true => xattr::list_deref(path),
+            false => xattr::list(path),
+        };
+        let get = |name: &OsStr| match dereference {
+            true => xattr::get_deref(path, name),
+            false => xattr::get(path, name),
+        };
+
+        let mut xattrs = Xattrs::default();
+        for name in names.into_iter().flatten() {
+            let Ok(Some(value)) = get(&name) else {
+                continue;
+            };
+
+            let name = name.to_string_lossy().into_owned();
+            if name == SELINUX {
+                let context = value.strip_suffix(b"\0").unwrap_or(&value);
+                xattrs.context = Some(String::from_utf8_lossy(context).into_owned());
+            }
+            xattrs.attributes.push((name, value.len()));
+        }
+
+        xattrs
+    }
+
+    /// What the long format puts after the mode: `+` for a file with an ACL, `@` for one with
+    /// other extended attributes and `.` for one that only has an SELinux context.
+    pub fn indicator(&self) -> Option<char> {
+        let names = || self.attributes.iter().map(|(name, _)| name.as_str());
+
+        if names().any(|name| name == ACL_ACCESS || name == ACL_DEFAULT) {
+            Some('+')
+        } else if names().any(|name| name != SELINUX) {
+            Some('@')
+        } else if self.context.is_some() {
+            Some('.')
+        } else {
+            None
+        }
+    }
+
+    /// The SELinux security context.
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    pub fn attributes(&self) -> &[(String, usize)] {
+        &self.attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Xattrs;
+
+    #[test]
+    fn test_indicator() {
+        let xattrs = |names: &[&str]| Xattrs {
+            attributes: names.iter().map(|name| (name.to_string(), 1)).collect(),
+            context: names
+                .contains(&"security.selinux")
+                .then(|| String::from("system_u:object_r:tmp_t:s0")),
+        };
+
+        assert_eq!(xattrs(&[]).indicator(), None);
+        assert_eq!(xattrs(&["security.selinux"]).indicator(), Some('.'));
+        assert_eq!(
+            xattrs(&["security.selinux", "user.origin"]).indicator(),
+            Some('@')
+        );
+        assert_eq!(
+            xattrs(&["user.origin", "system.posix_acl_access"]).indicator(),
+            Some('+')
+        );
+        assert_eq!(xattrs(&["system.posix_acl_default"]).indicator(), Some('+'));
+    }
+}
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE todo_list_items ADD COLUMN due_at DATETIME;
+ALTER TABLE todo_list_items ADD COLUMN priority INTEGER;
+ALTER TABLE todo_list_items ADD COLUMN notes TEXT;
+
+CREATE INDEX todo_list_items_due_at ON todo_list_items(due_at);
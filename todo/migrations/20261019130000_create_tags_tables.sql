@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE tags (
+  id INTEGER PRIMARY KEY,
+  name VARCHAR NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX tags_name_idx ON tags(name);
+
+CREATE TABLE todo_list_item_tags (
+  todo_list_item_id INTEGER NOT NULL,
+  tag_id INTEGER NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (todo_list_item_id, tag_id)
+);
+
+CREATE INDEX todo_list_item_tags_tag_id ON todo_list_item_tags(tag_id);
@@ -1,13 +1,28 @@
 use log::LevelFilter;
 use std::{env, str::FromStr, sync::Arc, thread};
 
+use clap::ValueEnum;
 use color_eyre::eyre::Result;
 use lazy_static::lazy_static;
-use models::{TodoListItemRow, TodoListRow};
-use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Pool, QueryBuilder, Sqlite, SqlitePool};
+use models::{Priority, TodoListItemRow, TodoListRow};
+use sqlx::{
+    sqlite::SqliteConnectOptions,
+    types::chrono::{DateTime, Local, NaiveDate, Utc},
+    ConnectOptions, Pool, QueryBuilder, Sqlite, SqlitePool,
+};
+
+use crate::due::start_of_day;
 
 pub mod models;
 
+/// Items are read with the tags they have, as `+tag` words, and the title of their list.
+const SELECT_ITEMS: &str = "SELECT todo_list_items.*,
+(SELECT group_concat('+' || tags.name, ' ' ORDER BY tags.name) FROM todo_list_item_tags
+  JOIN tags ON tags.id = todo_list_item_tags.tag_id
+  WHERE todo_list_item_tags.todo_list_item_id = todo_list_items.id) AS tags,
+todo_lists.title AS todo_list_title
+FROM todo_list_items JOIN todo_lists ON todo_lists.id = todo_list_items.todo_list_id";
+
 async fn initialize_connection() -> Result<Pool<Sqlite>> {
     let url = env::var("DATABASE_URL").expect("DATABASE_URL env var not found");
     let options = SqliteConnectOptions::from_str(&url)?
@@ -62,28 +77,192 @@ pub async fn create_todo_list(title: &str) -> Result<()> {
     Ok(())
 }
 
+/// The order items are listed in. Items without a due date or priority go last.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ItemSort {
+    Due,
+    Priority,
+    Created,
+}
+
+/// Which items are listed. Overdue and today go by the local date.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ItemFilter {
+    Overdue,
+    Today,
+    Pending,
+    Done,
+}
+
+/// Which of an item's dates a query's range goes by.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ItemDate {
+    Due,
+    Created,
+    Done,
+}
+
+/// What `todo query` looks for across every list. Items have to match all of it.
+#[derive(Debug)]
+pub struct ItemQuery {
+    /// Tags an item has to have, every one of them.
+    pub tags: Vec<String>,
+    /// Words its title or notes have to contain, in any case.
+    pub words: Vec<String>,
+    pub filter: Option<ItemFilter>,
+    /// The date `from` and `to` are compared with. Both days are part of the range.
+    pub date: ItemDate,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub sort: Option<ItemSort>,
+    pub with_deleted: bool,
+}
+
 pub async fn get_todo_list_items(todo_id: u32, with_deleted: bool) -> Result<Vec<TodoListItemRow>> {
-    let mut qb = QueryBuilder::new("SELECT * FROM todo_list_items WHERE todo_list_id = ");
+    get_todo_list_items_sorted(todo_id, with_deleted, None, None).await
+}
+
+pub async fn get_todo_list_items_sorted(
+    todo_id: u32,
+    with_deleted: bool,
+    sort: Option<ItemSort>,
+    filter: Option<ItemFilter>,
+) -> Result<Vec<TodoListItemRow>> {
+    let mut qb = QueryBuilder::new(SELECT_ITEMS);
+    qb.push(" WHERE todo_list_items.todo_list_id = ");
     qb.push_bind(todo_id);
 
     if !with_deleted {
-        qb.push(" AND deleted_at IS NULL");
+        qb.push(" AND todo_list_items.deleted_at IS NULL");
     }
 
+    push_filter(&mut qb, filter);
+    push_sort(&mut qb, sort);
+
     Ok(qb.build_query_as().fetch_all(&(*get_connection())).await?)
 }
 
-pub async fn add_todo_list_items(todo_list_id: u32, items: &[String]) -> Result<()> {
-    add_todo_list_items_dyn(todo_list_id, items, false).await?;
+pub async fn get_todo_list_items_by_ids(ids: &[u32]) -> Result<Vec<TodoListItemRow>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    Ok(())
+    let mut qb = QueryBuilder::new(SELECT_ITEMS);
+    qb.push(" WHERE todo_list_items.id IN (");
+    let mut separated = qb.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    qb.push(")");
+    push_sort(&mut qb, None);
+
+    Ok(qb.build_query_as().fetch_all(&(*get_connection())).await?)
+}
+
+/// Items from every list that isn't deleted, or from all of them with `with_deleted`.
+pub async fn query_todo_list_items(query: &ItemQuery) -> Result<Vec<TodoListItemRow>> {
+    let mut qb = QueryBuilder::new(SELECT_ITEMS);
+    qb.push(" WHERE TRUE");
+
+    if !query.with_deleted {
+        qb.push(" AND todo_lists.deleted_at IS NULL AND todo_list_items.deleted_at IS NULL");
+    }
+
+    for tag in &query.tags {
+        qb.push(
+            " AND EXISTS (SELECT 1 FROM todo_list_item_tags JOIN tags ON tags.id = todo_list_item_tags.tag_id",
+        );
+        qb.push(
+            " WHERE todo_list_item_tags.todo_list_item_id = todo_list_items.id AND tags.name = ",
+        );
+        qb.push_bind(tag.clone()).push(")");
+    }
+
+    for word in &query.words {
+        let pattern = format!(
+            "%{}%",
+            word.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        qb.push(" AND (todo_list_items.title LIKE ");
+        qb.push_bind(pattern.clone());
+        qb.push(" ESCAPE '\\' OR todo_list_items.notes LIKE ");
+        qb.push_bind(pattern);
+        qb.push(" ESCAPE '\\')");
+    }
+
+    let column = match query.date {
+        ItemDate::Due => "todo_list_items.due_at",
+        ItemDate::Created => "todo_list_items.created_at",
+        ItemDate::Done => "todo_list_items.done_at",
+    };
+    if let Some(from) = query.from {
+        qb.push(format!(" AND datetime({}) >= datetime(", column));
+        qb.push_bind(start_of_day(from)).push(")");
+    }
+    if let Some(to) = query.to {
+        qb.push(format!(" AND datetime({}) < datetime(", column));
+        qb.push_bind(start_of_day(to.succ_opt().unwrap_or(to)))
+            .push(")");
+    }
+
+    push_filter(&mut qb, query.filter);
+    push_sort(&mut qb, query.sort);
+
+    Ok(qb.build_query_as().fetch_all(&(*get_connection())).await?)
+}
+
+fn push_filter(qb: &mut QueryBuilder<'_, Sqlite>, filter: Option<ItemFilter>) {
+    // datetime() reads the timestamps sqlite writes and the ones sqlx binds alike
+    let today = Local::now().date_naive();
+
+    match filter {
+        Some(ItemFilter::Overdue) => {
+            qb.push(" AND todo_list_items.done_at IS NULL");
+            qb.push(" AND datetime(todo_list_items.due_at) < datetime(");
+            qb.push_bind(start_of_day(today)).push(")");
+        }
+        Some(ItemFilter::Today) => {
+            qb.push(" AND datetime(todo_list_items.due_at) >= datetime(");
+            qb.push_bind(start_of_day(today)).push(")");
+            qb.push(" AND datetime(todo_list_items.due_at) < datetime(");
+            qb.push_bind(start_of_day(today.succ_opt().unwrap_or(today)))
+                .push(")");
+        }
+        Some(ItemFilter::Pending) => {
+            qb.push(" AND todo_list_items.done_at IS NULL");
+        }
+        Some(ItemFilter::Done) => {
+            qb.push(" AND todo_list_items.done_at IS NOT NULL");
+        }
+        None => {}
+    }
+}
+
+fn push_sort(qb: &mut QueryBuilder<'_, Sqlite>, sort: Option<ItemSort>) {
+    qb.push(match sort {
+        Some(ItemSort::Due) => {
+            " ORDER BY todo_list_items.due_at IS NULL, datetime(todo_list_items.due_at), todo_list_items.id"
+        }
+        Some(ItemSort::Priority) => {
+            " ORDER BY todo_list_items.priority IS NULL, todo_list_items.priority DESC, todo_list_items.id"
+        }
+        Some(ItemSort::Created) => {
+            " ORDER BY datetime(todo_list_items.created_at), todo_list_items.id"
+        }
+        None => " ORDER BY todo_list_items.id",
+    });
 }
 
 pub async fn add_todo_list_items_returning(
     todo_list_id: u32,
     items: &[String],
+    due_at: Option<DateTime<Utc>>,
+    priority: Option<Priority>,
+    notes: Option<&str>,
 ) -> Result<Vec<TodoListItemRow>> {
-    let added_rows = add_todo_list_items_dyn(todo_list_id, items, true)
+    let added_rows = add_todo_list_items_dyn(todo_list_id, items, due_at, priority, notes, true)
         .await?
         .expect("A vector of added rows should be returned");
 
@@ -93,17 +272,25 @@ pub async fn add_todo_list_items_returning(
 async fn add_todo_list_items_dyn(
     todo_list_id: u32,
     items: &[String],
+    due_at: Option<DateTime<Utc>>,
+    priority: Option<Priority>,
+    notes: Option<&str>,
     returning: bool,
 ) -> Result<Option<Vec<TodoListItemRow>>> {
     if items.is_empty() {
         return Ok(Some(Vec::new()));
     }
 
-    let mut qb: QueryBuilder<'_, Sqlite> =
-        QueryBuilder::new("INSERT INTO todo_list_items (todo_list_id, title) ");
+    let mut qb: QueryBuilder<'_, Sqlite> = QueryBuilder::new(
+        "INSERT INTO todo_list_items (todo_list_id, title, due_at, priority, notes) ",
+    );
 
     qb.push_values(items.iter(), |mut qb, item| {
-        qb.push_bind(todo_list_id).push_bind(item);
+        qb.push_bind(todo_list_id)
+            .push_bind(item)
+            .push_bind(due_at)
+            .push_bind(priority)
+            .push_bind(notes);
     });
 
     if returning {
@@ -135,15 +322,49 @@ pub async fn edit_todo_list_items(
     qb.push("\nUPDATE todo_list_items SET title = (SELECT title FROM tmp WHERE todo_list_items.id = tmp.id)");
     qb.push("\nWHERE deleted_at IS NULL AND id IN (SELECT id FROM tmp) AND todo_list_id = ");
     qb.push_bind(todo_list_id);
-
-    let query = qb.sql();
-    println!("{}", query);
+    qb.push(" RETURNING *");
 
     let rows = qb.build_query_as().fetch_all(&(*get_connection())).await?;
 
     Ok(rows)
 }
 
+/// Gives an item exactly `tags`, creating the ones that don't exist yet. It's all one
+/// transaction, so an item never ends up with its old tags gone and the new ones missing.
+pub async fn set_todo_list_item_tags(todo_list_item_id: u32, tags: &[String]) -> Result<()> {
+    let mut tx = get_connection().begin().await?;
+
+    let mut qb = QueryBuilder::new("DELETE FROM todo_list_item_tags WHERE todo_list_item_id = ");
+    qb.push_bind(todo_list_item_id);
+    qb.build().execute(&mut *tx).await?;
+
+    if tags.is_empty() {
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    let mut qb: QueryBuilder<'_, Sqlite> = QueryBuilder::new("INSERT INTO tags (name) ");
+    qb.push_values(tags, |mut qb, tag| {
+        qb.push_bind(tag);
+    });
+    qb.push(" ON CONFLICT (name) DO NOTHING");
+    qb.build().execute(&mut *tx).await?;
+
+    let mut qb =
+        QueryBuilder::new("INSERT INTO todo_list_item_tags (todo_list_item_id, tag_id) SELECT ");
+    qb.push_bind(todo_list_item_id);
+    qb.push(", id FROM tags WHERE name IN (");
+    let mut separated = qb.separated(", ");
+    for tag in tags {
+        separated.push_bind(tag);
+    }
+    qb.push(")");
+    qb.build().execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
 pub async fn clear_todo_list_items(todo_list_id: u32) -> Result<()> {
     let mut qb = QueryBuilder::new("UPDATE todo_list_items SET deleted_at = datetime('now') WHERE deleted_at IS NULL AND todo_list_id = ");
     qb.push_bind(todo_list_id);
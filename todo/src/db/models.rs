@@ -1,8 +1,23 @@
+use clap::ValueEnum;
+use derive_more::Display;
 use sqlx::{
     prelude::FromRow,
     types::chrono::{DateTime, Utc},
+    Type,
 };
 
+/// How much an item matters, stored as a number so that items sort by it.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, PartialOrd, Ord, Type, ValueEnum)]
+#[repr(i64)]
+pub enum Priority {
+    #[display("low")]
+    Low = 1,
+    #[display("medium")]
+    Medium = 2,
+    #[display("high")]
+    High = 3,
+}
+
 #[derive(FromRow)]
 pub struct TodoListRow {
     id: u32,
@@ -39,6 +54,13 @@ pub struct TodoListItemRow {
     id: u32,
     title: String,
     done_at: Option<DateTime<Utc>>,
+    due_at: Option<DateTime<Utc>>,
+    priority: Option<Priority>,
+    notes: Option<String>,
+    #[sqlx(default)]
+    tags: Option<String>,
+    #[sqlx(default)]
+    todo_list_title: Option<String>,
     deleted_at: Option<DateTime<Utc>>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
@@ -61,6 +83,33 @@ impl TodoListItemRow {
         self.done_at.is_some()
     }
 
+    pub fn due_at(&self) -> Option<&DateTime<Utc>> {
+        self.due_at.as_ref()
+    }
+
+    /// An item is overdue when it isn't done and was due before the day that starts at `today`.
+    pub fn is_overdue(&self, today: &DateTime<Utc>) -> bool {
+        !self.is_done() && self.due_at.is_some_and(|due_at| due_at < *today)
+    }
+
+    pub fn priority(&self) -> Option<Priority> {
+        self.priority
+    }
+
+    pub fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// The item's tags as `+tag` words, when it has any and was read with them.
+    pub fn tags(&self) -> Option<&str> {
+        self.tags.as_deref()
+    }
+
+    /// The title of the list the item is on, when it was read with it.
+    pub fn todo_list_title(&self) -> Option<&str> {
+        self.todo_list_title.as_deref()
+    }
+
     pub fn deleted_at(&self) -> Option<&DateTime<Utc>> {
         self.deleted_at.as_ref()
     }
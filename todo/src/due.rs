@@ -0,0 +1,87 @@
+use chrono::{
+    DateTime, Datelike, Days, Local, Months, NaiveDate, NaiveTime, TimeZone, Utc, Weekday,
+};
+
+/// Reads a due date the way people write them: `2026-11-01`, `today`, `tomorrow`, a weekday
+/// like `fri` or `next friday` for the first one after today, `next week`, `next month`, or
+/// `in 3 days`, `in 2 weeks` and `in 1 month`.
+pub fn parse_due_date(input: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let input = input.trim().to_lowercase();
+
+    if let Ok(date) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let date = match words.as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["next", "week"] => today.checked_add_days(Days::new(7)),
+        ["next", "month"] => today.checked_add_months(Months::new(1)),
+        ["in", count, unit] => count.parse().ok().and_then(|count: u32| match *unit {
+            "day" | "days" => today.checked_add_days(Days::new(count.into())),
+            "week" | "weeks" => today.checked_add_days(Days::new(u64::from(count) * 7)),
+            "month" | "months" => today.checked_add_months(Months::new(count)),
+            _ => None,
+        }),
+        ["next", weekday] | [weekday] => weekday
+            .parse()
+            .ok()
+            .and_then(|weekday| next_weekday(today, weekday)),
+        _ => None,
+    };
+
+    date.ok_or_else(|| {
+        format!(
+            "invalid due date '{}'; expected YYYY-MM-DD, today, tomorrow, a weekday, next week or in N days",
+            input
+        )
+    })
+}
+
+/// When `date` starts in the local time zone, which is what due dates are compared against.
+pub fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map_or_else(|| midnight.and_utc(), |start| start.to_utc())
+}
+
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> Option<NaiveDate> {
+    let days = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+
+    today.checked_add_days(Days::new(match days {
+        0 => 7,
+        days => days.into(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::parse_due_date;
+
+    #[test]
+    fn test_parse_due_date() {
+        // a Monday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let due = |input: &str| parse_due_date(input, today).map(|date| date.to_string());
+
+        assert_eq!(due("2026-11-01"), Ok(String::from("2026-11-01")));
+        assert_eq!(due("today"), Ok(String::from("2026-10-19")));
+        assert_eq!(due("Tomorrow"), Ok(String::from("2026-10-20")));
+        assert_eq!(due("fri"), Ok(String::from("2026-10-23")));
+        assert_eq!(due("next fri"), Ok(String::from("2026-10-23")));
+        assert_eq!(due("next monday"), Ok(String::from("2026-10-26")));
+        assert_eq!(due("next week"), Ok(String::from("2026-10-26")));
+        assert_eq!(due("next month"), Ok(String::from("2026-11-19")));
+        assert_eq!(due("in 3 days"), Ok(String::from("2026-10-22")));
+        assert_eq!(due("in 2 weeks"), Ok(String::from("2026-11-02")));
+        assert!(due("someday").is_err());
+        assert!(due("in x days").is_err());
+        assert!(due("2026-13-01").is_err());
+    }
+}
@@ -1,16 +1,24 @@
 use std::fmt::Display;
 
 use clap::{Args, Parser, Subcommand};
-use color_eyre::{eyre::Result, owo_colors::OwoColorize};
+use color_eyre::{
+    eyre::Result,
+    owo_colors::{OwoColorize, Style},
+};
 use db::{
     create_todo_list,
-    models::{TodoListItemRow, TodoListRow},
+    models::{Priority, TodoListItemRow, TodoListRow},
+    ItemDate, ItemFilter, ItemQuery, ItemSort,
 };
 use derive_more::Display;
-use sqlx::types::chrono::Local;
+use due::{parse_due_date, start_of_day};
+use sqlx::types::chrono::{Local, NaiveDate};
 use std::error::Error;
+use tags::{as_tag, split_tags};
 
 mod db;
+mod due;
+mod tags;
 
 #[derive(Debug, Parser)]
 pub struct TodoCli {
@@ -27,6 +35,7 @@ enum SubCommand {
     Done(DoneArgs),
     Edit(EditArgs),
     List(ListArgs),
+    Query(QueryArgs),
     Remove(RemoveArgs),
     Restore(RestoreArgs),
     ShowLists(ShowListsArgs),
@@ -41,6 +50,19 @@ struct AddArgs {
 
     #[arg(required = true, num_args = 1..)]
     items: Vec<String>,
+
+    #[arg(
+        long = "due",
+        value_parser = parse_due_date_arg,
+        help = "When the items are due: YYYY-MM-DD, today, tomorrow, a weekday like 'next fri', next week or 'in 3 days'"
+    )]
+    due: Option<NaiveDate>,
+
+    #[arg(long = "priority", help = "How much the items matter")]
+    priority: Option<Priority>,
+
+    #[arg(long = "notes", help = "Notes kept with the items")]
+    notes: Option<String>,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -88,10 +110,64 @@ struct ListArgs {
     )]
     all: bool,
 
+    #[arg(
+        long = "sort",
+        help = "Order the items by due date, priority or creation"
+    )]
+    sort: Option<ItemSort>,
+
+    #[arg(
+        long = "filter",
+        help = "Only list the items that are overdue, due today, pending or done"
+    )]
+    filter: Option<ItemFilter>,
+
     #[arg(required = true)]
     todo_list_title: String,
 }
 
+#[derive(Args, Clone, Debug)]
+#[command()]
+struct QueryArgs {
+    #[arg(
+        short = 'a',
+        long = "all",
+        help = "Search deleted items and lists too",
+        default_value = "false"
+    )]
+    all: bool,
+
+    #[arg(
+        long = "filter",
+        help = "Only find the items that are overdue, due today, pending or done"
+    )]
+    filter: Option<ItemFilter>,
+
+    #[arg(
+        long = "date",
+        default_value = "due",
+        help = "Which date --from and --to go by"
+    )]
+    date: ItemDate,
+
+    #[arg(long = "from", value_parser = parse_due_date_arg, help = "Only find items dated this day or later")]
+    from: Option<NaiveDate>,
+
+    #[arg(long = "to", value_parser = parse_due_date_arg, help = "Only find items dated this day or earlier")]
+    to: Option<NaiveDate>,
+
+    #[arg(
+        long = "sort",
+        help = "Order the items by due date, priority or creation"
+    )]
+    sort: Option<ItemSort>,
+
+    #[arg(
+        help = "+tag to find items with the tag, any other word to find items whose title or notes contain it"
+    )]
+    terms: Vec<String>,
+}
+
 #[derive(Args, Clone, Debug)]
 #[command()]
 struct RemoveArgs {
@@ -137,21 +213,50 @@ enum CliError {
 
 impl Error for CliError {}
 
+fn parse_due_date_arg(input: &str) -> std::result::Result<NaiveDate, String> {
+    parse_due_date(input, Local::now().date_naive())
+}
+
 impl TodoCli {
-    pub async fn exec(&self) -> Result<CliOutput> {
+    pub async fn exec(&self) -> Result<CliOutput<'_>> {
         match &self.subcommand {
             SubCommand::Add(AddArgs {
                 todo_list_title,
                 items,
+                due,
+                priority,
+                notes,
             }) => {
                 let todo_list = Self::try_get_todo_list_by_title(todo_list_title).await?;
                 if items.is_empty() {
                     return Err(CliError::ArgsError("No items passed to be added").into());
                 }
 
-                let added_rows = db::add_todo_list_items_returning(todo_list.id(), items).await?;
+                let (titles, tags): (Vec<String>, Vec<Vec<String>>) =
+                    items.iter().map(|item| split_tags(item)).unzip();
+                if titles.iter().any(String::is_empty) {
+                    return Err(CliError::ArgsError("Items need a title besides their tags").into());
+                }
+
+                let mut added_rows = db::add_todo_list_items_returning(
+                    todo_list.id(),
+                    &titles,
+                    due.map(start_of_day),
+                    *priority,
+                    notes.as_deref(),
+                )
+                .await?;
+
+                // ids go up in the order the items were inserted
+                added_rows.sort_by_key(TodoListItemRow::id);
+                for (row, tags) in added_rows.iter().zip(&tags) {
+                    db::set_todo_list_item_tags(row.id(), tags).await?;
+                }
 
-                Ok(CliOutput::TodoItemsOutput(added_rows, false))
+                let ids: Vec<u32> = added_rows.iter().map(TodoListItemRow::id).collect();
+                let rows = db::get_todo_list_items_by_ids(&ids).await?;
+
+                Ok(CliOutput::TodoItemsOutput(rows, false))
             }
             SubCommand::Clear(ClearArgs { todo_list_title }) => {
                 let todo_list = Self::try_get_todo_list_by_title(todo_list_title).await?;
@@ -208,6 +313,7 @@ impl TodoCli {
                 }
 
                 let mut id_and_item_pairs = Vec::new();
+                let mut id_and_tags_pairs = Vec::new();
 
                 for half_idx in 0..(items.iter().len() / 2) {
                     let idx = half_idx * 2;
@@ -222,25 +328,76 @@ impl TodoCli {
                             .into());
                         }
                     };
-                    let item = items[idx + 1].clone();
+                    let (item, tags) = split_tags(&items[idx + 1]);
+                    if item.is_empty() {
+                        return Err(
+                            CliError::ArgsError("Items need a title besides their tags").into()
+                        );
+                    }
 
                     id_and_item_pairs.push((id, item));
+                    id_and_tags_pairs.push((id, tags));
                 }
 
                 let edited_rows =
                     db::edit_todo_list_items(todo_list.id(), &id_and_item_pairs).await?;
 
-                Ok(CliOutput::TodoItemsOutput(edited_rows, false))
+                // the title is replaced as a whole, and so are the tags in it
+                for row in &edited_rows {
+                    if let Some((_, tags)) =
+                        id_and_tags_pairs.iter().find(|(id, _)| *id == row.id())
+                    {
+                        db::set_todo_list_item_tags(row.id(), tags).await?;
+                    }
+                }
+
+                let ids: Vec<u32> = edited_rows.iter().map(TodoListItemRow::id).collect();
+                let rows = db::get_todo_list_items_by_ids(&ids).await?;
+
+                Ok(CliOutput::TodoItemsOutput(rows, false))
             }
             SubCommand::List(ListArgs {
                 all,
+                sort,
+                filter,
                 todo_list_title,
             }) => {
                 let todo_list = Self::try_get_todo_list_by_title(todo_list_title).await?;
-                let rows = db::get_todo_list_items(todo_list.id(), *all).await?;
+                let rows =
+                    db::get_todo_list_items_sorted(todo_list.id(), *all, *sort, *filter).await?;
 
                 Ok(CliOutput::TodoItemsOutput(rows, *all))
             }
+            SubCommand::Query(QueryArgs {
+                all,
+                filter,
+                date,
+                from,
+                to,
+                sort,
+                terms,
+            }) => {
+                let mut query = ItemQuery {
+                    tags: Vec::new(),
+                    words: Vec::new(),
+                    filter: *filter,
+                    date: *date,
+                    from: *from,
+                    to: *to,
+                    sort: *sort,
+                    with_deleted: *all,
+                };
+                for term in terms {
+                    match as_tag(term) {
+                        Some(tag) => query.tags.push(tag),
+                        None => query.words.push(term.clone()),
+                    }
+                }
+
+                let rows = db::query_todo_list_items(&query).await?;
+
+                Ok(CliOutput::QueryOutput(rows, *all))
+            }
             SubCommand::Remove(RemoveArgs {
                 todo_list_title,
                 ids,
@@ -305,6 +462,7 @@ impl TodoCli {
 
 pub enum CliOutput<'a> {
     TodoItemsOutput(Vec<TodoListItemRow>, bool),
+    QueryOutput(Vec<TodoListItemRow>, bool),
     TodoListsOutput(Vec<TodoListRow>),
     TodoListCreatedOutput(&'a str),
     TodoListClearedOutput(&'a str),
@@ -314,7 +472,10 @@ impl Display for CliOutput<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::TodoItemsOutput(todo_list_items, with_deleted) => {
-                fmt_todo_list_items(todo_list_items, f, *with_deleted)
+                fmt_todo_list_items(todo_list_items, f, *with_deleted, false)
+            }
+            Self::QueryOutput(todo_list_items, with_deleted) => {
+                fmt_todo_list_items(todo_list_items, f, *with_deleted, true)
             }
             Self::TodoListsOutput(todo_lists) => fmt_todo_lists(todo_lists, f),
             Self::TodoListCreatedOutput(title) => {
@@ -346,15 +507,23 @@ fn fmt_todo_list_items(
     todo_list_items: &[TodoListItemRow],
     f: &mut std::fmt::Formatter<'_>,
     with_deleted: bool,
+    with_lists: bool,
 ) -> std::fmt::Result {
+    if todo_list_items.is_empty() && with_lists {
+        return write!(f, "No items found");
+    }
     if todo_list_items.is_empty() {
         return write!(f, "list is empty");
     }
 
+    if with_lists {
+        write!(f, " {:<15}|", "list")?;
+    }
+
     write!(
         f,
-        " {:<5}| {:<30}| {:<30}| {:<30}",
-        "id", "item", "created at", "done at"
+        " {:<5}| {:<30}| {:<8}| {:<16}| {:<30}| {:<30}",
+        "id", "item", "priority", "due", "created at", "done at"
     )?;
 
     if with_deleted {
@@ -363,11 +532,17 @@ fn fmt_todo_list_items(
 
     writeln!(f)?;
 
+    if with_lists {
+        write!(f, "{}|", "=".repeat(16))?;
+    }
+
     write!(
         f,
-        "{}|{}|{}|{}",
+        "{}|{}|{}|{}|{}|{}",
         "=".repeat(6),
         "=".repeat(31),
+        "=".repeat(9),
+        "=".repeat(17),
         "=".repeat(31),
         "=".repeat(31)
     )?;
@@ -376,11 +551,23 @@ fn fmt_todo_list_items(
         write!(f, "|{}", "=".repeat(31))?;
     }
 
+    let today = start_of_day(Local::now().date_naive());
+
     for todo in todo_list_items {
         let id = todo.id();
-        let title = todo.title();
+        let title = match todo.tags() {
+            Some(tags) => format!("{} {}", todo.title(), tags),
+            None => todo.title().to_string(),
+        };
+        let priority = todo.priority().map_or(String::new(), |p| p.to_string());
         let created_at = todo.created_at().with_timezone(&Local).to_rfc2822();
 
+        let due_at: Box<dyn Display> = if let Some(due_at) = todo.due_at() {
+            Box::new(due_at.with_timezone(&Local).format("%a %Y-%m-%d"))
+        } else {
+            Box::new("")
+        };
+
         let done_at: Box<dyn Display> = if let Some(done_at) = todo.done_at() {
             Box::new(done_at.with_timezone(&Local).to_rfc2822())
         } else {
@@ -408,15 +595,38 @@ fn fmt_todo_list_items(
             (id, title)
         };
 
+        let (title, due_at): (Box<dyn Display>, Box<dyn Display>) = if todo.is_overdue(&today) {
+            let overdue = Style::new().red().bold();
+            (
+                Box::new(title.style(overdue)),
+                Box::new(due_at.style(overdue)),
+            )
+        } else {
+            (title, due_at)
+        };
+
+        writeln!(f)?;
+        if with_lists {
+            write!(f, " {:<15}|", todo.todo_list_title().unwrap_or_default())?;
+        }
+
         write!(
             f,
-            "\n {:<5}| {:<30}| {:<30}| {:<30}",
-            id, title, created_at, done_at,
+            " {:<5}| {:<30}| {:<8}| {:<16}| {:<30}| {:<30}",
+            id, title, priority, due_at, created_at, done_at,
         )?;
 
         if with_deleted {
             write!(f, "| {:<30}", deleted_at)?;
         }
+
+        if let Some(notes) = todo.notes() {
+            writeln!(f)?;
+            if with_lists {
+                write!(f, " {:<15}|", "")?;
+            }
+            write!(f, " {:<5}| {}", "", notes.dimmed())?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,61 @@
+/// Takes the `+tag` words out of an item's title. Tags are lowercased and kept once each, in the
+/// order they first show up; the rest of the title is kept with single spaces between words.
+pub fn split_tags(title: &str) -> (String, Vec<String>) {
+    let mut words = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+
+    for word in title.split_whitespace() {
+        match as_tag(word) {
+            Some(tag) if tags.contains(&tag) => {}
+            Some(tag) => tags.push(tag),
+            None => words.push(word),
+        }
+    }
+
+    (words.join(" "), tags)
+}
+
+/// A tag starts with a letter after the `+`, so that `+1` stays part of the title.
+pub fn as_tag(word: &str) -> Option<String> {
+    let tag = word.strip_prefix('+')?;
+
+    let starts_with_letter = tag.chars().next().is_some_and(char::is_alphabetic);
+    let valid = tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'));
+
+    (starts_with_letter && valid).then(|| tag.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_tags;
+
+    #[test]
+    fn test_split_tags() {
+        let split = |title: &str| {
+            let (title, tags) = split_tags(title);
+            (title, tags.join(","))
+        };
+
+        assert_eq!(
+            split("ship  the release +Release +backend"),
+            (
+                String::from("ship the release"),
+                String::from("release,backend")
+            )
+        );
+        assert_eq!(
+            split("+ops renew certs +ops"),
+            (String::from("renew certs"), String::from("ops"))
+        );
+        assert_eq!(
+            split("give +1 to a + b and +c++"),
+            (String::from("give +1 to a + b and +c++"), String::new())
+        );
+        assert_eq!(
+            split("+team/web-ui fix it"),
+            (String::from("fix it"), String::from("team/web-ui"))
+        );
+    }
+}